@@ -0,0 +1,4 @@
+// make cargo rebuild when a migration is added, as they're embedded with sqlx::migrate!
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
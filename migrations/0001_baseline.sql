@@ -0,0 +1,38 @@
+-- baseline: the schema as it was created by init_db.sql
+-- IF NOT EXISTS so databases created with init_db.sql can adopt it
+
+CREATE TABLE IF NOT EXISTS item (
+    id SERIAL PRIMARY KEY,
+    name_ VARCHAR(255),
+    price VARCHAR(255),
+    price_number FLOAT4,
+    price_currency VARCHAR(255),
+    vendor_link VARCHAR(255),
+    type_ VARCHAR(255),
+    -- todo consider varchar with max limit
+    descr TEXT,
+    added_timestamp BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS item_pic (
+    id SERIAL PRIMARY KEY,
+    item_id INTEGER REFERENCES item(id),
+    -- todo consider varchar with max limit
+    url TEXT NOT NULL
+);
+
+
+CREATE TABLE IF NOT EXISTS notification_outbox (
+    id SERIAL PRIMARY KEY,
+    item_id INTEGER REFERENCES item(id),
+    kind VARCHAR(255) NOT NULL,
+    old_price_number FLOAT4,
+    new_price_number FLOAT4,
+    -- pending, delivered or failed
+    status VARCHAR(255) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_timestamp BIGINT NOT NULL,
+    next_attempt_timestamp BIGINT NOT NULL,
+    delivered_timestamp BIGINT
+);
@@ -0,0 +1,9 @@
+-- prices as exact decimals with an ISO 4217 currency code
+-- the display string (item.price) is formatted by the api per locale now
+
+ALTER TABLE item ALTER COLUMN price_number TYPE NUMERIC(12, 2) USING ROUND(price_number::NUMERIC, 2);
+UPDATE item SET price_currency = 'EUR' WHERE price_currency = '€';
+ALTER TABLE item DROP COLUMN price;
+
+ALTER TABLE notification_outbox ALTER COLUMN old_price_number TYPE NUMERIC(12, 2) USING ROUND(old_price_number::NUMERIC, 2);
+ALTER TABLE notification_outbox ALTER COLUMN new_price_number TYPE NUMERIC(12, 2) USING ROUND(new_price_number::NUMERIC, 2);
@@ -0,0 +1,12 @@
+-- products are upserted by their vendor link, so it has to be unique
+-- drop older duplicates (same product scraped more than once), keeping the latest row
+
+DELETE FROM item_pic WHERE item_id IN (
+    SELECT i.id FROM item i WHERE EXISTS (SELECT 1 FROM item newer WHERE newer.vendor_link = i.vendor_link AND newer.id > i.id)
+);
+DELETE FROM notification_outbox WHERE item_id IN (
+    SELECT i.id FROM item i WHERE EXISTS (SELECT 1 FROM item newer WHERE newer.vendor_link = i.vendor_link AND newer.id > i.id)
+);
+DELETE FROM item i WHERE EXISTS (SELECT 1 FROM item newer WHERE newer.vendor_link = i.vendor_link AND newer.id > i.id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS item_vendor_link_key ON item (vendor_link);
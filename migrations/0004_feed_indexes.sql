@@ -0,0 +1,8 @@
+-- indexes for the items feed (items::load_items)
+
+-- the feed is paged by added_timestamp and filtered by type and price, so the index is scanned
+-- in timestamp order and the filters are checked on the index entries
+CREATE INDEX IF NOT EXISTS item_feed_idx ON item (added_timestamp, type_, price_number);
+
+-- pictures are looked up per returned item
+CREATE INDEX IF NOT EXISTS item_pic_item_id_idx ON item_pic (item_id);
@@ -0,0 +1,29 @@
+-- history of scrape runs, to see when data went stale or a selector broke
+
+CREATE TABLE IF NOT EXISTS scrape_run (
+    id SERIAL PRIMARY KEY,
+    -- running, succeeded or failed
+    status VARCHAR(255) NOT NULL,
+    started_timestamp BIGINT NOT NULL,
+    finished_timestamp BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS scrape_run_target (
+    id SERIAL PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES scrape_run(id),
+    type_ VARCHAR(255) NOT NULL,
+    url TEXT NOT NULL,
+    started_timestamp BIGINT NOT NULL,
+    finished_timestamp BIGINT NOT NULL,
+    pages_visited INTEGER NOT NULL,
+    items_found INTEGER NOT NULL,
+    items_inserted INTEGER NOT NULL,
+    items_updated INTEGER NOT NULL,
+    items_skipped INTEGER NOT NULL,
+    items_failed INTEGER NOT NULL,
+    -- count of result cards per field that couldn't be extracted, e.g. {"price": 3}
+    extraction_failures JSONB NOT NULL,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS scrape_run_target_run_id_idx ON scrape_run_target (run_id);
@@ -0,0 +1,5 @@
+-- which selector profile version and variants extracted a target's results
+
+ALTER TABLE scrape_run_target ADD COLUMN IF NOT EXISTS selector_version INTEGER;
+-- count per field and matching variant, e.g. {"price_whole": {".a-price-whole": 48}}
+ALTER TABLE scrape_run_target ADD COLUMN IF NOT EXISTS selector_matches JSONB NOT NULL DEFAULT '{}';
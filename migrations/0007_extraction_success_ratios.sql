@@ -0,0 +1,7 @@
+-- share of result cards each field was extracted from, to catch selectors that stopped matching
+
+ALTER TABLE scrape_run_target ADD COLUMN IF NOT EXISTS result_cards INTEGER NOT NULL DEFAULT 0;
+-- e.g. {"link": 0.96, "name": 0.96, "price": 0.9, "img": 0.96}
+ALTER TABLE scrape_run_target ADD COLUMN IF NOT EXISTS success_ratios JSONB NOT NULL DEFAULT '{}';
+-- the same per page, in the order the pages were visited
+ALTER TABLE scrape_run_target ADD COLUMN IF NOT EXISTS page_success_ratios JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,8 @@
+-- the marketplace an item was scraped from, so users are served the items of their region
+
+-- everything scraped before came from amazon.de
+ALTER TABLE item ADD COLUMN IF NOT EXISTS marketplace VARCHAR(255) NOT NULL DEFAULT 'amazon.de';
+
+-- the feed filters by marketplace too, keep checking the filters on the index entries
+DROP INDEX IF EXISTS item_feed_idx;
+CREATE INDEX item_feed_idx ON item (added_timestamp, type_, price_number, marketplace);
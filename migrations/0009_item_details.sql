@@ -0,0 +1,10 @@
+-- details taken from the product pages by the enrichment job (bin/enrich)
+
+-- the gallery order, pictures saved with the search results come first
+ALTER TABLE item_pic ADD COLUMN IF NOT EXISTS position INTEGER NOT NULL DEFAULT 0;
+
+-- null until the details page was visited
+ALTER TABLE item ADD COLUMN IF NOT EXISTS details_refreshed_timestamp BIGINT;
+
+-- the job picks the items without or with the oldest details
+CREATE INDEX IF NOT EXISTS item_details_refreshed_idx ON item (details_refreshed_timestamp NULLS FIRST);
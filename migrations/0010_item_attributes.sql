@@ -0,0 +1,9 @@
+-- attributes of the search result cards (brand, rating, review_count, prime)
+-- and of the details pages (material, colour, brand if the card has none)
+ALTER TABLE item ADD COLUMN IF NOT EXISTS brand TEXT;
+ALTER TABLE item ADD COLUMN IF NOT EXISTS material TEXT;
+ALTER TABLE item ADD COLUMN IF NOT EXISTS colour TEXT;
+-- stars out of 5
+ALTER TABLE item ADD COLUMN IF NOT EXISTS rating DOUBLE PRECISION;
+ALTER TABLE item ADD COLUMN IF NOT EXISTS review_count INTEGER;
+ALTER TABLE item ADD COLUMN IF NOT EXISTS prime BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,25 @@
+-- variants (sizes, colours) Amazon lists as separate products sharing a parent ASIN
+-- the feed shows one card per parent with its variants attached
+
+ALTER TABLE item ADD COLUMN IF NOT EXISTS parent_asin TEXT;
+
+-- finds the first saved item of a parent, the one standing for it in the feed
+CREATE INDEX IF NOT EXISTS item_parent_asin_idx ON item (parent_asin, marketplace, added_timestamp)
+    WHERE parent_asin IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS item_variant (
+    id SERIAL PRIMARY KEY,
+    marketplace TEXT NOT NULL,
+    parent_asin TEXT NOT NULL,
+    asin TEXT NOT NULL,
+    -- dimension -> value, e.g. {"color": "Silber", "size": "54"}
+    dimensions JSONB NOT NULL,
+    price_number NUMERIC(12, 2) NOT NULL,
+    -- ISO 4217 code
+    price_currency VARCHAR(255) NOT NULL,
+    img TEXT,
+    vendor_link TEXT NOT NULL,
+    UNIQUE (marketplace, asin)
+);
+
+CREATE INDEX IF NOT EXISTS item_variant_parent_idx ON item_variant (parent_asin, marketplace);
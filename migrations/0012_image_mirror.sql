@@ -0,0 +1,19 @@
+-- pictures mirrored from the marketplaces' cdns, see image_mirror.rs
+-- stored by content hash, so the same image from several urls is kept once
+
+CREATE TABLE IF NOT EXISTS image (
+    -- sha256 of the original, hex
+    hash TEXT PRIMARY KEY,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    -- each stored as webp and jpeg
+    thumbnail_widths INTEGER[] NOT NULL,
+    created_timestamp BIGINT NOT NULL
+);
+
+ALTER TABLE item_pic ADD COLUMN IF NOT EXISTS image_hash TEXT REFERENCES image(hash);
+-- failed downloads, retried until the mirror's max attempts
+ALTER TABLE item_pic ADD COLUMN IF NOT EXISTS mirror_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE item_pic ADD COLUMN IF NOT EXISTS mirror_error TEXT;
+
+CREATE INDEX IF NOT EXISTS item_pic_unmirrored_idx ON item_pic (url) WHERE image_hash IS NULL;
@@ -0,0 +1,9 @@
+-- dead vendor links and pictures, see link_checker.rs
+-- 'ok', 'dead' or 'error', NULL until checked
+
+ALTER TABLE item ADD COLUMN IF NOT EXISTS link_status TEXT;
+ALTER TABLE item ADD COLUMN IF NOT EXISTS link_checked_timestamp BIGINT;
+ALTER TABLE item_pic ADD COLUMN IF NOT EXISTS status TEXT;
+ALTER TABLE item_pic ADD COLUMN IF NOT EXISTS checked_timestamp BIGINT;
+
+CREATE INDEX IF NOT EXISTS item_link_checked_idx ON item (link_checked_timestamp NULLS FIRST, id);
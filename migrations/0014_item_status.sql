@@ -0,0 +1,13 @@
+-- item lifecycle, see items::set_item_status
+-- only published items are in the feed, the others stay resolvable by id for wishlists
+
+-- everything saved before was in the feed
+ALTER TABLE item ADD COLUMN IF NOT EXISTS status VARCHAR(32) NOT NULL DEFAULT 'published'
+    CHECK (status IN ('draft', 'published', 'hidden', 'unavailable', 'removed'));
+ALTER TABLE item ADD COLUMN IF NOT EXISTS status_reason TEXT;
+-- null until the status is changed after the item was saved
+ALTER TABLE item ADD COLUMN IF NOT EXISTS status_timestamp BIGINT;
+
+-- the feed only scans published items
+DROP INDEX IF EXISTS item_feed_idx;
+CREATE INDEX item_feed_idx ON item (added_timestamp, type_, price_number, marketplace) WHERE status = 'published';
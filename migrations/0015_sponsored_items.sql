@@ -0,0 +1,4 @@
+-- products only found as ads in the search results (sponsored), see scrapper::is_sponsored_link
+-- cleared once a product is found as an organic result
+
+ALTER TABLE item ADD COLUMN IF NOT EXISTS is_sponsored BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,7 @@
+-- how likely a scraped product belongs to its category, see relevance.rs
+-- null for items saved before or of categories without rules
+-- products with a low confidence are saved as drafts, the review queue
+
+ALTER TABLE item ADD COLUMN IF NOT EXISTS relevance_confidence DOUBLE PRECISION;
+
+CREATE INDEX IF NOT EXISTS item_review_queue_idx ON item (relevance_confidence NULLS FIRST, id) WHERE status = 'draft';
@@ -0,0 +1,3 @@
+-- bracelets were saved as armband before scrape_jobs.toml named the category bracelet
+
+UPDATE item SET type_ = 'bracelet' WHERE type_ = 'armband';
@@ -0,0 +1,7 @@
+-- an item's pictures are upserted by url when it's enriched again, see details::save_item_details
+-- so their mirrored images and link checks are kept
+
+DELETE FROM item_pic ip
+WHERE EXISTS (SELECT 1 FROM item_pic older WHERE older.item_id = ip.item_id AND older.url = ip.url AND older.id < ip.id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS item_pic_item_url_key ON item_pic (item_id, url);
@@ -0,0 +1,2 @@
+-- the feed falls back to the default marketplace while the client's ones have no items, see main::feed_marketplaces
+CREATE INDEX IF NOT EXISTS item_published_marketplace_idx ON item (marketplace) WHERE status = 'published';
@@ -0,0 +1,6 @@
+-- items whose vendor link is dead are unavailable instead of published, see link_checker::check_items
+-- the reason is link_checker::DEAD_LINK_REASON, so they're published again if the link comes back
+UPDATE item
+SET status = 'unavailable', status_reason = 'vendor link is gone',
+    status_timestamp = (EXTRACT(EPOCH FROM now()) * 1000000)::BIGINT
+WHERE status = 'published' AND link_status = 'dead';
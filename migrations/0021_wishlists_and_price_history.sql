@@ -0,0 +1,27 @@
+-- price drops are notified for wishlisted items, judged from their price history, see notifications.rs
+
+-- the items each user liked, by the user id the clients and the push gateway share
+CREATE TABLE IF NOT EXISTS wishlist_item (
+    user_id VARCHAR(128) NOT NULL,
+    item_id INTEGER NOT NULL REFERENCES item (id) ON DELETE CASCADE,
+    added_timestamp BIGINT NOT NULL,
+    PRIMARY KEY (user_id, item_id)
+);
+CREATE INDEX IF NOT EXISTS wishlist_item_item_id_idx ON wishlist_item (item_id);
+
+-- a row per scraped price that differs from the item's last one
+CREATE TABLE IF NOT EXISTS item_price_history (
+    id SERIAL PRIMARY KEY,
+    item_id INTEGER NOT NULL REFERENCES item (id) ON DELETE CASCADE,
+    price_number NUMERIC(12, 2) NOT NULL,
+    price_currency VARCHAR(255) NOT NULL,
+    recorded_timestamp BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS item_price_history_item_idx ON item_price_history (item_id, recorded_timestamp);
+
+-- the saved prices are the first ones of the history
+INSERT INTO item_price_history (item_id, price_number, price_currency, recorded_timestamp)
+SELECT id, price_number, price_currency, COALESCE(added_timestamp, 0)
+FROM item
+WHERE price_number IS NOT NULL AND price_currency IS NOT NULL
+    AND NOT EXISTS (SELECT 1 FROM item_price_history h WHERE h.item_id = item.id);
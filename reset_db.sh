@@ -1,2 +1,3 @@
-psql -h 127.0.0.1 -p 5433 -U tester -d bikematch -f ./init_db.sql
+psql -h 127.0.0.1 -p 5433 -U tester -d bikematch -c "DROP SCHEMA public CASCADE; CREATE SCHEMA public;"
+cargo run -- migrate 5433
 # psql -h 127.0.0.1 -p 5433 -U tester -d bikematch -f ./populate_db.sql
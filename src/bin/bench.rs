@@ -0,0 +1,170 @@
+// Seeds a synthetic catalog and measures the latency of the items feed query.
+// cargo run --release --bin bench -- [port] [items] [iterations]
+// The seeded rows are removed again at the end.
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use newbicycle_backend::{
+    init_pool,
+    items::{load_items, to_db_filters, Filters, LOAD_ITEMS_QUERY},
+    schema,
+};
+use sqlx::{Pool, Postgres};
+
+const BENCH_LINK_PREFIX: &str = "bench://";
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+    let port = args.get(1).map(String::as_str).unwrap_or("5433");
+    let items: i64 = args
+        .get(2)
+        .map_or(100_000, |a| a.parse().expect("invalid items"));
+    let iterations: usize = args
+        .get(3)
+        .map_or(500, |a| a.parse().expect("invalid iterations"));
+
+    let pool = init_pool(port).await;
+    schema::migrate(&pool)
+        .await
+        .expect("error running migrations");
+
+    // start after everything that exists already, so the seeded items are what's paged through
+    let start_timestamp = chrono::Utc::now().timestamp_micros();
+    remove_seeded(&pool).await;
+    println!("seeding {} items...", items);
+    seed(&pool, items, start_timestamp).await;
+
+    let filter_combinations = [
+        (vec![], vec![]),
+        (vec!["necklace"], vec![1]),
+        (vec!["ring", "earring"], vec![2, 3]),
+        (vec!["bracelet"], vec![4]),
+    ];
+
+    let mut durations = vec![];
+    for i in 0..iterations {
+        let (types, prices) = &filter_combinations[i % filter_combinations.len()];
+        let filters = Filters {
+            type_: types.iter().map(|t| t.to_string()).collect(),
+            price: prices.clone(),
+            ..Default::default()
+        };
+        // spread the pages across the catalog
+        let after_timestamp = start_timestamp + (i as i64 * 7919) % items;
+
+        let start = Instant::now();
+        load_items(&pool, after_timestamp, &to_db_filters(&filters, &[]))
+            .await
+            .expect("error loading items");
+        durations.push(start.elapsed());
+    }
+
+    durations.sort();
+    println!("iterations: {}", durations.len());
+    println!("p50: {:?}", percentile(&durations, 50));
+    println!("p99: {:?}", percentile(&durations, 99));
+
+    explain(&pool, start_timestamp + items / 2).await;
+
+    remove_seeded(&pool).await;
+}
+
+fn percentile(sorted_durations: &[Duration], percentile: usize) -> Duration {
+    let index = (sorted_durations.len() * percentile / 100).min(sorted_durations.len() - 1);
+    sorted_durations[index]
+}
+
+async fn seed(pool: &Pool<Postgres>, items: i64, start_timestamp: i64) {
+    sqlx::query(
+        r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
+SELECT
+    'bench item ' || n,
+    ((n * 37) % 20000) / 100.0,
+    'EUR',
+    $1 || n,
+    (ARRAY['necklace', 'bracelet', 'ring', 'earring'])[(n % 4 + 1)::INT],
+    $2 + n,
+    ''
+FROM generate_series(1, $3) AS n;
+"#,
+    )
+    .bind(BENCH_LINK_PREFIX)
+    .bind(start_timestamp)
+    .bind(items)
+    .execute(pool)
+    .await
+    .expect("error seeding items");
+
+    // 3 pictures per item
+    sqlx::query(
+        r#"
+INSERT INTO item_pic (item_id, url)
+SELECT i.id, 'https://doesntexist.com/' || i.id || '/' || n || '.jpg'
+FROM item i, generate_series(1, 3) AS n
+WHERE i.vendor_link LIKE $1 || '%';
+"#,
+    )
+    .bind(BENCH_LINK_PREFIX)
+    .execute(pool)
+    .await
+    .expect("error seeding pictures");
+
+    sqlx::query("ANALYZE item, item_pic;")
+        .execute(pool)
+        .await
+        .expect("error analyzing");
+}
+
+async fn explain(pool: &Pool<Postgres>, after_timestamp: i64) {
+    let filters = to_db_filters(
+        &Filters {
+            type_: vec!["necklace".to_string()],
+            price: vec![1],
+            ..Default::default()
+        },
+        &["amazon.de".to_string()],
+    );
+
+    let plan: Vec<(String,)> = sqlx::query_as(&format!("EXPLAIN ANALYZE {}", LOAD_ITEMS_QUERY))
+        .bind(after_timestamp)
+        .bind(filters.type_)
+        .bind(filters.price_min)
+        .bind(filters.price_max)
+        .bind(filters.marketplace)
+        .bind(filters.brand)
+        .bind(filters.min_rating)
+        .bind(filters.material)
+        .bind(filters.colour)
+        .bind(filters.prime_only)
+        .fetch_all(pool)
+        .await
+        .expect("error explaining query");
+
+    println!("plan:");
+    for (line,) in plan {
+        println!("{}", line);
+    }
+}
+
+async fn remove_seeded(pool: &Pool<Postgres>) {
+    sqlx::query(
+        r#"
+DELETE FROM item_pic WHERE item_id IN (SELECT id FROM item WHERE vendor_link LIKE $1 || '%');
+"#,
+    )
+    .bind(BENCH_LINK_PREFIX)
+    .execute(pool)
+    .await
+    .expect("error removing seeded pictures");
+
+    sqlx::query("DELETE FROM item WHERE vendor_link LIKE $1 || '%';")
+        .bind(BENCH_LINK_PREFIX)
+        .execute(pool)
+        .await
+        .expect("error removing seeded items");
+}
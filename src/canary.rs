@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+
+use crate::scrapper::ExtractionField;
+
+/// The share of result cards each field was extracted from, between 0 and 1.
+pub type SuccessRatios = BTreeMap<ExtractionField, f64>;
+
+const FIELDS: [ExtractionField; 4] = [
+    ExtractionField::Link,
+    ExtractionField::Name,
+    ExtractionField::Price,
+    ExtractionField::Img,
+];
+
+/// Without result cards every ratio is 0, a result selector that stopped matching is drift too.
+pub fn success_ratios(
+    result_cards: u32,
+    extraction_failures: &HashMap<ExtractionField, u32>,
+) -> SuccessRatios {
+    FIELDS
+        .iter()
+        .map(|field| {
+            let ratio = if result_cards == 0 {
+                0.0
+            } else {
+                let failures = extraction_failures.get(field).copied().unwrap_or_default();
+                (result_cards - failures.min(result_cards)) as f64 / result_cards as f64
+            };
+            (*field, ratio)
+        })
+        .collect()
+}
+
+/// Errors if a field was extracted from less result cards than its minimum ratio,
+/// which means a selector most likely broke and the results shouldn't be saved.
+pub fn check(
+    ratios: &SuccessRatios,
+    min_success_ratio: &HashMap<ExtractionField, f64>,
+) -> Result<()> {
+    let drifted: Vec<String> = FIELDS
+        .iter()
+        .filter_map(|field| {
+            let min = min_success_ratio.get(field)?;
+            let ratio = ratios.get(field).copied().unwrap_or_default();
+            (ratio < *min).then(|| {
+                format!(
+                    "{:?} extracted from {:.0}% of result cards (min {:.0}%)",
+                    field,
+                    ratio * 100.0,
+                    min * 100.0
+                )
+            })
+        })
+        .collect();
+
+    if drifted.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("selector drift: {}", drifted.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, fs, path::Path};
+
+    use crate::{
+        canary::{check, success_ratios},
+        marketplace::Marketplaces,
+        scrape_jobs::ScrapeJobs,
+        scrapper::{parse_search_page, ExtractionField},
+        selectors::SelectorProfiles,
+    };
+
+    #[test]
+    fn fails_below_min_ratio() {
+        let ratios = success_ratios(
+            10,
+            &HashMap::from([(ExtractionField::Price, 3), (ExtractionField::Img, 1)]),
+        );
+        assert_eq!(ratios[&ExtractionField::Link], 1.0);
+        assert_eq!(ratios[&ExtractionField::Price], 0.7);
+
+        let min = HashMap::from([(ExtractionField::Price, 0.8), (ExtractionField::Img, 0.8)]);
+        let error = check(&ratios, &min).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "selector drift: Price extracted from 70% of result cards (min 80%)"
+        );
+
+        assert!(check(&ratios, &HashMap::from([(ExtractionField::Price, 0.7)])).is_ok());
+    }
+
+    #[test]
+    fn no_result_cards_is_drift() {
+        let ratios = success_ratios(0, &HashMap::new());
+        assert!(check(&ratios, &HashMap::from([(ExtractionField::Link, 0.5)])).is_err());
+        assert!(check(&ratios, &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn fixture_pages_pass_shipped_thresholds() {
+        let jobs = ScrapeJobs::parse(include_str!("../scrape_jobs.toml")).unwrap();
+        let profiles = SelectorProfiles::parse(include_str!("../selectors.toml")).unwrap();
+        let marketplaces = Marketplaces::parse(include_str!("../marketplaces.toml")).unwrap();
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/search");
+
+        for fixture in ["amazon_de_ringe_page1", "amazon_de_ringe_last_page"] {
+            let html = fs::read_to_string(fixtures.join(format!("{}.html", fixture))).unwrap();
+            let page = parse_search_page(
+                &html,
+                profiles.get("amazon").unwrap(),
+                marketplaces.get("amazon.de").unwrap(),
+            )
+            .unwrap();
+            assert!(
+                check(&page.success_ratios(), &jobs.min_success_ratio).is_ok(),
+                "{} fails the thresholds: {:?}",
+                fixture,
+                page.success_ratios()
+            );
+        }
+    }
+}
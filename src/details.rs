@@ -0,0 +1,411 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use chrono::Utc;
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres, Transaction};
+use thirtyfour::WebDriver;
+
+use crate::{
+    marketplace::Marketplaces,
+    scrapper::{extract_product_details, ProductDetails, ProductVariant},
+    selectors::SelectorProfiles,
+};
+
+/// A saved item whose details page wasn't visited yet, or too long ago.
+#[derive(Debug, FromRow)]
+pub struct ItemToEnrich {
+    pub id: i32,
+    pub vendor_link: String,
+    pub marketplace: String,
+}
+
+/// Items without details first, then the ones refreshed longest ago.
+/// Items refreshed at or after `refreshed_before` (micros) are left out.
+pub async fn load_items_to_enrich(
+    pool: &Pool<Postgres>,
+    refreshed_before: i64,
+    limit: i64,
+) -> Result<Vec<ItemToEnrich>> {
+    let items = sqlx::query_as::<_, ItemToEnrich>(
+        r#"
+SELECT id, vendor_link, marketplace
+FROM item
+WHERE details_refreshed_timestamp IS NULL OR details_refreshed_timestamp < $1
+ORDER BY details_refreshed_timestamp NULLS FIRST, added_timestamp DESC
+LIMIT $2;
+"#,
+    )
+    .bind(refreshed_before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(items)
+}
+
+/// The items among the given vendor links whose details page wasn't visited yet, e.g. the ones a scrape just found.
+pub async fn load_new_items_to_enrich(
+    pool: &Pool<Postgres>,
+    links: &[String],
+) -> Result<Vec<ItemToEnrich>> {
+    let items = sqlx::query_as::<_, ItemToEnrich>(
+        r#"
+SELECT id, vendor_link, marketplace
+FROM item
+WHERE vendor_link = ANY($1) AND details_refreshed_timestamp IS NULL
+ORDER BY added_timestamp DESC;
+"#,
+    )
+    .bind(links)
+    .fetch_all(pool)
+    .await?;
+    Ok(items)
+}
+
+/// Replaces the item's gallery pictures with the gallery (keeping the ones still in it) and its description and attributes with the details ones,
+/// and the variants of its parent with the listed ones. The picture saved with the search result stays first.
+/// Empty details keep what's saved, so a selector that stopped matching doesn't wipe items.
+pub async fn save_item_details(
+    pool: &Pool<Postgres>,
+    item_id: i32,
+    details: &ProductDetails,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    if !details.images.is_empty() {
+        // the pictures are kept by url, with their mirrored images and link checks
+        // the search result's picture is at position 0, the gallery follows it
+        let mut seen = HashSet::new();
+        let images: Vec<&String> = details
+            .images
+            .iter()
+            .filter(|url| seen.insert(url.as_str()))
+            .collect();
+        sqlx::query("DELETE FROM item_pic WHERE item_id = $1 AND position > 0 AND url <> ALL($2);")
+            .bind(item_id)
+            .bind(&images)
+            .execute(&mut *tx)
+            .await?;
+
+        let positions: Vec<i32> = (1..=images.len() as i32).collect();
+        sqlx::query(
+            r#"
+INSERT INTO item_pic (item_id, url, position)
+SELECT $1, url, position FROM UNNEST($2::TEXT[], $3::INTEGER[]) AS t(url, position)
+ON CONFLICT (item_id, url) DO UPDATE SET position = EXCLUDED.position;
+"#,
+        )
+        .bind(item_id)
+        .bind(&images)
+        .bind(&positions)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let (marketplace,): (String,) = sqlx::query_as(
+        r#"
+UPDATE item
+SET descr = COALESCE(NULLIF($2, ''), descr), details_refreshed_timestamp = $3,
+    brand = COALESCE(brand, $4), material = COALESCE($5, material), colour = COALESCE($6, colour),
+    parent_asin = COALESCE($7, parent_asin)
+WHERE id = $1
+RETURNING marketplace;
+"#,
+    )
+    .bind(item_id)
+    .bind(&details.descr)
+    .bind(Utc::now().timestamp_micros())
+    .bind(&details.brand)
+    .bind(&details.material)
+    .bind(&details.colour)
+    .bind(&details.parent_asin)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if let (Some(parent_asin), false) = (&details.parent_asin, details.variants.is_empty()) {
+        save_variants(&mut tx, &marketplace, parent_asin, &details.variants).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// the variants are listed on the page of each of them, the latest list replaces the saved one
+async fn save_variants(
+    tx: &mut Transaction<'_, Postgres>,
+    marketplace: &str,
+    parent_asin: &str,
+    variants: &[ProductVariant],
+) -> Result<()> {
+    sqlx::query("DELETE FROM item_variant WHERE marketplace = $1 AND parent_asin = $2;")
+        .bind(marketplace)
+        .bind(parent_asin)
+        .execute(&mut **tx)
+        .await?;
+
+    let asins: Vec<String> = variants.iter().map(|v| v.asin.clone()).collect();
+    let dimensions: Vec<String> = variants
+        .iter()
+        .map(|v| serde_json::to_string(&v.dimensions))
+        .collect::<serde_json::Result<_>>()?;
+    let prices: Vec<BigDecimal> = variants.iter().map(|v| v.price.amount.clone()).collect();
+    let currencies: Vec<String> = variants.iter().map(|v| v.price.currency.clone()).collect();
+    let imgs: Vec<Option<String>> = variants.iter().map(|v| v.img.clone()).collect();
+    let links: Vec<String> = variants.iter().map(|v| v.details_link.clone()).collect();
+
+    // an asin moved to another parent is taken over
+    sqlx::query(
+        r#"
+INSERT INTO item_variant (marketplace, parent_asin, asin, dimensions, price_number, price_currency, img, vendor_link)
+SELECT $1, $2, asin, dimensions::JSONB, price_number, price_currency, img, vendor_link
+FROM UNNEST($3::TEXT[], $4::TEXT[], $5::NUMERIC[], $6::TEXT[], $7::TEXT[], $8::TEXT[])
+    AS t(asin, dimensions, price_number, price_currency, img, vendor_link)
+ON CONFLICT (marketplace, asin) DO UPDATE
+SET parent_asin = EXCLUDED.parent_asin, dimensions = EXCLUDED.dimensions, price_number = EXCLUDED.price_number,
+    price_currency = EXCLUDED.price_currency, img = EXCLUDED.img, vendor_link = EXCLUDED.vendor_link;
+"#,
+    )
+    .bind(marketplace)
+    .bind(parent_asin)
+    .bind(&asins)
+    .bind(&dimensions)
+    .bind(&prices)
+    .bind(&currencies)
+    .bind(&imgs)
+    .bind(&links)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct EnrichSummary {
+    pub enriched: u32,
+    // items whose details page couldn't be visited or parsed, they're retried next time
+    pub failed: u32,
+}
+
+/// Visits the details page of each item and saves its gallery and description.
+/// A failing item is logged and skipped, the others are still enriched.
+pub async fn enrich_items(
+    driver: &WebDriver,
+    pool: &Pool<Postgres>,
+    items: &[ItemToEnrich],
+    marketplaces: &Marketplaces,
+    selectors: &SelectorProfiles,
+) -> EnrichSummary {
+    let mut summary = EnrichSummary::default();
+
+    for item in items {
+        match enrich_item(driver, pool, item, marketplaces, selectors).await {
+            Ok(()) => summary.enriched += 1,
+            Err(e) => {
+                warn!(
+                    "error enriching item {} ({}): {}",
+                    item.id, item.vendor_link, e
+                );
+                summary.failed += 1;
+            }
+        }
+    }
+
+    info!("enriched items: {:?}", summary);
+
+    summary
+}
+
+async fn enrich_item(
+    driver: &WebDriver,
+    pool: &Pool<Postgres>,
+    item: &ItemToEnrich,
+    marketplaces: &Marketplaces,
+    selectors: &SelectorProfiles,
+) -> Result<()> {
+    let marketplace = marketplaces.get(&item.marketplace)?;
+    let profile = selectors.get(&marketplace.selector_profile)?;
+    let details = extract_product_details(driver, profile, marketplace, &item.vendor_link).await?;
+    save_item_details(pool, item.id, &details).await
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use std::{collections::BTreeMap, str::FromStr};
+
+    use bigdecimal::BigDecimal;
+    use sqlx::{Pool, Postgres};
+
+    use crate::{
+        details::save_item_details,
+        init_pool,
+        items::{load_items, to_db_filters, Filters},
+        scrapper::{Price, ProductDetails, ProductVariant},
+    };
+
+    #[tokio::test]
+    async fn saves_gallery_and_description() -> Result<()> {
+        let pool = init_pool("5433").await;
+        let (item_id,): (i32,) = sqlx::query_as(
+            r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
+VALUES ('mock product with details', 10, 'EUR', 'https://foo.bar/details', 'mock', 1, '')
+ON CONFLICT (vendor_link) DO UPDATE SET descr = ''
+RETURNING id;
+"#,
+        )
+        .fetch_one(&pool)
+        .await?;
+        // the picture of the search result, saved by the scrape
+        let search_picture = "https://doesntexist.com/search.png".to_string();
+        sqlx::query("DELETE FROM item_pic WHERE item_id = $1;")
+            .bind(item_id)
+            .execute(&pool)
+            .await?;
+        sqlx::query("INSERT INTO item_pic (item_id, url) VALUES ($1, $2);")
+            .bind(item_id)
+            .bind(&search_picture)
+            .execute(&pool)
+            .await?;
+
+        let details = ProductDetails {
+            name: "mock product with details".to_string(),
+            images: vec![
+                "https://doesntexist.com/2.png".to_string(),
+                "https://doesntexist.com/1.png".to_string(),
+            ],
+            descr: "first bullet\nsecond bullet".to_string(),
+            material: Some("Edelstahl".to_string()),
+            ..Default::default()
+        };
+        save_item_details(&pool, item_id, &details).await?;
+
+        let pictures: Vec<(String,)> =
+            sqlx::query_as("SELECT url FROM item_pic WHERE item_id = $1 ORDER BY position, id")
+                .bind(item_id)
+                .fetch_all(&pool)
+                .await?;
+        let pictures: Vec<String> = pictures.into_iter().map(|(url,)| url).collect();
+        assert_eq!(
+            pictures,
+            [vec![search_picture.clone()], details.images.clone()].concat()
+        );
+
+        // empty details keep the saved ones
+        save_item_details(&pool, item_id, &ProductDetails::default()).await?;
+        let (descr, material, refreshed): (String, Option<String>, Option<i64>) = sqlx::query_as(
+            "SELECT descr, material, details_refreshed_timestamp FROM item WHERE id = $1",
+        )
+        .bind(item_id)
+        .fetch_one(&pool)
+        .await?;
+        assert_eq!(descr, details.descr);
+        assert_eq!(material, details.material);
+        assert!(refreshed.is_some());
+        let (pictures_count,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM item_pic WHERE item_id = $1")
+                .bind(item_id)
+                .fetch_one(&pool)
+                .await?;
+        assert_eq!(pictures_count, 3);
+
+        // a refreshed gallery keeps the mirror and link check state of the pictures still in it
+        sqlx::query(
+            r#"
+UPDATE item_pic SET mirror_attempts = 1, status = 'ok', checked_timestamp = 1
+WHERE item_id = $1 AND url = $2;
+"#,
+        )
+        .bind(item_id)
+        .bind(&details.images[1])
+        .execute(&pool)
+        .await?;
+        let refreshed = ProductDetails {
+            images: vec![
+                "https://doesntexist.com/3.png".to_string(),
+                "https://doesntexist.com/1.png".to_string(),
+                "https://doesntexist.com/3.png".to_string(),
+            ],
+            ..Default::default()
+        };
+        save_item_details(&pool, item_id, &refreshed).await?;
+        let pictures: Vec<(String, i32, i32, Option<String>)> = sqlx::query_as(
+            "SELECT url, position, mirror_attempts, status FROM item_pic WHERE item_id = $1 ORDER BY position;",
+        )
+        .bind(item_id)
+        .fetch_all(&pool)
+        .await?;
+        assert_eq!(
+            pictures,
+            vec![
+                (search_picture, 0, 0, None),
+                ("https://doesntexist.com/3.png".to_string(), 1, 0, None),
+                (
+                    "https://doesntexist.com/1.png".to_string(),
+                    2,
+                    1,
+                    Some("ok".to_string())
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    async fn insert_mock_item(pool: &Pool<Postgres>, link: &str, added: i64) -> Result<i32> {
+        let (item_id,): (i32,) = sqlx::query_as(
+            r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
+VALUES ('mock product with variants', 10, 'EUR', $1, 'mock-variants', $2, '')
+ON CONFLICT (vendor_link) DO UPDATE SET parent_asin = NULL
+RETURNING id;
+"#,
+        )
+        .bind(link)
+        .bind(added)
+        .fetch_one(pool)
+        .await?;
+        Ok(item_id)
+    }
+
+    #[tokio::test]
+    async fn groups_variants_under_first_item() -> Result<()> {
+        let pool = init_pool("5433").await;
+        let first = insert_mock_item(&pool, "https://foo.bar/variants/a", 1).await?;
+        let second = insert_mock_item(&pool, "https://foo.bar/variants/b", 2).await?;
+
+        let variant = |asin: &str, size: &str| ProductVariant {
+            asin: asin.to_string(),
+            dimensions: BTreeMap::from([("size".to_string(), size.to_string())]),
+            price: Price {
+                amount: BigDecimal::from_str("10.00").unwrap(),
+                currency: "EUR".to_string(),
+            },
+            img: None,
+            details_link: format!("https://foo.bar/dp/{}", asin),
+        };
+        let details = ProductDetails {
+            name: "mock product with variants".to_string(),
+            parent_asin: Some("MOCKPARENT".to_string()),
+            variants: vec![variant("MOCKSIZE52", "52"), variant("MOCKSIZE54", "54")],
+            ..Default::default()
+        };
+        save_item_details(&pool, second, &details).await?;
+        save_item_details(&pool, first, &details).await?;
+
+        let filters = Filters {
+            type_: vec!["mock-variants".to_string()],
+            ..Default::default()
+        };
+        let items = load_items(&pool, 0, &to_db_filters(&filters, &[])).await?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, first.to_string());
+        let asins: Vec<&str> = items[0].variants.iter().map(|v| v.asin.as_str()).collect();
+        assert_eq!(asins, vec!["MOCKSIZE52", "MOCKSIZE54"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::{anyhow, Result};
+use scraper::{ElementRef, Selector};
+
+/// The parts of a DOM the extractors need, so they don't depend on where the html comes from.
+pub trait HtmlNode: Sized {
+    /// Descendants matching a css selector.
+    fn find_all(&self, css: &str) -> Result<Vec<Self>>;
+
+    fn attr(&self, name: &str) -> Option<String>;
+
+    /// Text content with whitespace collapsed, like a browser renders it.
+    fn text(&self) -> String;
+
+    /// The first descendant matching a css selector, error if there's none.
+    fn find(&self, css: &str) -> Result<Self> {
+        self.find_all(css)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no element found for: {}", css))
+    }
+}
+
+/// Static html, parsed with the scraper crate.
+/// Pages loaded with WebDriver are parsed from their source too, so the same extraction runs live and on saved pages.
+impl HtmlNode for ElementRef<'_> {
+    fn find_all(&self, css: &str) -> Result<Vec<Self>> {
+        let selector =
+            Selector::parse(css).map_err(|e| anyhow!("invalid selector {}: {:?}", css, e))?;
+        Ok(ElementRef::select(self, &selector).collect())
+    }
+
+    fn attr(&self, name: &str) -> Option<String> {
+        self.value().attr(name).map(|value| value.to_string())
+    }
+
+    fn text(&self) -> String {
+        ElementRef::text(self)
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
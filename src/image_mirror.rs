@@ -0,0 +1,563 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage};
+use log::{info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+
+/// Where the mirrored images are kept, by key like "originals/<hash>".
+pub trait ImageStorage {
+    fn put(&self, key: &str, bytes: Vec<u8>) -> impl Future<Output = Result<()>> + Send;
+
+    /// None if nothing is stored under the key.
+    fn get(&self, key: &str) -> impl Future<Output = Result<Option<Vec<u8>>>> + Send;
+}
+
+/// Keeps the images as files below a directory, keys are paths relative to it.
+#[derive(Debug, Clone)]
+pub struct DiskStorage {
+    root: PathBuf,
+}
+
+impl DiskStorage {
+    pub fn new(root: &Path) -> DiskStorage {
+        DiskStorage {
+            root: root.to_path_buf(),
+        }
+    }
+}
+
+impl ImageStorage for DiskStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&path, bytes).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.root.join(key)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Keeps the images in memory, for tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    pub images: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl ImageStorage for MemoryStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<()> {
+        self.images
+            .lock()
+            .expect("memory storage lock poisoned")
+            .insert(key.to_string(), bytes);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .images
+            .lock()
+            .expect("memory storage lock poisoned")
+            .get(key)
+            .cloned())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    Webp,
+    Jpeg,
+}
+
+impl ThumbnailFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Webp => "webp",
+            ThumbnailFormat::Jpeg => "jpg",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Webp => "image/webp",
+            ThumbnailFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// Webp if the client's `Accept` header lists it, jpeg otherwise.
+    pub fn for_accept(accept: &str) -> ThumbnailFormat {
+        if accept.contains("image/webp") {
+            ThumbnailFormat::Webp
+        } else {
+            ThumbnailFormat::Jpeg
+        }
+    }
+}
+
+const FORMATS: [ThumbnailFormat; 2] = [ThumbnailFormat::Webp, ThumbnailFormat::Jpeg];
+
+pub fn original_key(hash: &str) -> String {
+    format!("originals/{}", hash)
+}
+
+pub fn thumbnail_key(hash: &str, width: u32, format: ThumbnailFormat) -> String {
+    format!("thumbs/{}/{}.{}", hash, width, format.extension())
+}
+
+#[derive(Debug, Clone)]
+pub struct MirrorConfig {
+    // thumbnail widths, images narrower than a width get one thumbnail in their own width instead
+    pub widths: Vec<u32>,
+    pub jpeg_quality: u8,
+    // 0 to 100
+    pub webp_quality: f32,
+    // failed downloads are retried in later runs until this many attempts
+    pub max_attempts: i32,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        MirrorConfig {
+            widths: vec![320, 640, 960],
+            jpeg_quality: 80,
+            webp_quality: 75.0,
+            max_attempts: 3,
+        }
+    }
+}
+
+/// A downloaded image with its thumbnails, ready to be stored.
+pub struct ProcessedImage {
+    // sha256 of the original, hex
+    pub hash: String,
+    pub width: u32,
+    pub height: u32,
+    // (width, format, encoded image)
+    pub thumbnails: Vec<(u32, ThumbnailFormat, Vec<u8>)>,
+}
+
+impl ProcessedImage {
+    pub fn thumbnail_widths(&self) -> Vec<u32> {
+        let mut widths: Vec<u32> = self.thumbnails.iter().map(|(width, _, _)| *width).collect();
+        widths.dedup();
+        widths
+    }
+}
+
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn encode(image: &DynamicImage, format: ThumbnailFormat, config: &MirrorConfig) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    match format {
+        // lossy, the image crate's webp encoder is lossless only and makes photos larger than jpeg
+        ThumbnailFormat::Webp => {
+            let rgba = image.to_rgba8();
+            let webp = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                .encode_simple(false, config.webp_quality)
+                .map_err(|e| anyhow!("error encoding webp: {:?}", e))?;
+            bytes.extend_from_slice(&webp);
+        }
+        // jpeg has no alpha channel
+        ThumbnailFormat::Jpeg => JpegEncoder::new_with_quality(&mut bytes, config.jpeg_quality)
+            .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8()))?,
+    }
+    Ok(bytes)
+}
+
+/// Decodes an image and encodes its thumbnails, images are never scaled up.
+pub fn process_image(original: &[u8], config: &MirrorConfig) -> Result<ProcessedImage> {
+    let image = image::load_from_memory(original)?;
+    let (width, height) = (image.width(), image.height());
+
+    let mut widths: Vec<u32> = config.widths.iter().map(|w| (*w).min(width)).collect();
+    widths.sort();
+    widths.dedup();
+
+    let mut thumbnails = vec![];
+    for thumbnail_width in widths {
+        let thumbnail = if thumbnail_width < width {
+            image.resize(thumbnail_width, u32::MAX, FilterType::Lanczos3)
+        } else {
+            image.clone()
+        };
+        for format in FORMATS {
+            thumbnails.push((thumbnail_width, format, encode(&thumbnail, format, config)?));
+        }
+    }
+
+    Ok(ProcessedImage {
+        hash: content_hash(original),
+        width,
+        height,
+        thumbnails,
+    })
+}
+
+/// The thumbnail width to serve for a requested width, the smallest at least as wide, or the widest.
+pub fn pick_width(thumbnail_widths: &[u32], requested: u32) -> Option<u32> {
+    thumbnail_widths
+        .iter()
+        .copied()
+        .filter(|width| *width >= requested)
+        .min()
+        .or(thumbnail_widths.iter().copied().max())
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MirrorSummary {
+    pub mirrored: u32,
+    // downloads whose content was already mirrored from another url
+    pub reused: u32,
+    pub failed: u32,
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let res = client
+        .get(url)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        return Err(anyhow!("{} responded with: {}", url, res.status()));
+    }
+    Ok(res.bytes().await?.to_vec())
+}
+
+/// The urls of the pictures that aren't mirrored yet and have attempts left, oldest first.
+pub async fn load_pending_urls(
+    pool: &Pool<Postgres>,
+    config: &MirrorConfig,
+    limit: i64,
+) -> Result<Vec<String>> {
+    let urls: Vec<(String,)> = sqlx::query_as(
+        r#"
+SELECT url FROM item_pic
+WHERE image_hash IS NULL AND mirror_attempts < $1
+GROUP BY url
+ORDER BY MIN(id)
+LIMIT $2;
+"#,
+    )
+    .bind(config.max_attempts)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(urls.into_iter().map(|(url,)| url).collect())
+}
+
+/// Downloads each url once and stores the image with its thumbnails for all pictures with the url.
+/// A failing url is recorded on its pictures and retried in later runs, the others are still mirrored.
+pub async fn mirror_urls<S: ImageStorage>(
+    pool: &Pool<Postgres>,
+    storage: &S,
+    client: &reqwest::Client,
+    config: &MirrorConfig,
+    urls: &[String],
+) -> Result<MirrorSummary> {
+    let mut summary = MirrorSummary::default();
+    for url in urls {
+        match mirror_url(pool, storage, client, config, url).await {
+            Ok(true) => summary.mirrored += 1,
+            Ok(false) => summary.reused += 1,
+            Err(e) => {
+                warn!("error mirroring {}: {}", url, e);
+                summary.failed += 1;
+                sqlx::query(
+                    "UPDATE item_pic SET mirror_attempts = mirror_attempts + 1, mirror_error = $2 WHERE url = $1;",
+                )
+                .bind(url)
+                .bind(e.to_string())
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+
+    info!("mirrored images: {:?}", summary);
+
+    Ok(summary)
+}
+
+// returns whether the image was new, false if its content was mirrored before
+async fn mirror_url<S: ImageStorage>(
+    pool: &Pool<Postgres>,
+    storage: &S,
+    client: &reqwest::Client,
+    config: &MirrorConfig,
+    url: &str,
+) -> Result<bool> {
+    let original = download(client, url).await?;
+    let hash = content_hash(&original);
+
+    let known: Option<(String,)> = sqlx::query_as("SELECT hash FROM image WHERE hash = $1;")
+        .bind(&hash)
+        .fetch_optional(pool)
+        .await?;
+
+    if known.is_none() {
+        // decoding and encoding take a while, off the async workers
+        let config = config.clone();
+        let (processed, original) = tokio::task::spawn_blocking(move || {
+            process_image(&original, &config).map(|processed| (processed, original))
+        })
+        .await??;
+
+        let thumbnail_widths = processed.thumbnail_widths();
+        for (width, format, bytes) in processed.thumbnails {
+            storage
+                .put(&thumbnail_key(&hash, width, format), bytes)
+                .await?;
+        }
+        storage.put(&original_key(&hash), original).await?;
+
+        // recorded last, so an image row means all its files are stored
+        sqlx::query(
+            r#"
+INSERT INTO image (hash, width, height, thumbnail_widths, created_timestamp)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (hash) DO NOTHING;
+"#,
+        )
+        .bind(&hash)
+        .bind(processed.width as i32)
+        .bind(processed.height as i32)
+        .bind(
+            thumbnail_widths
+                .iter()
+                .map(|width| *width as i32)
+                .collect::<Vec<i32>>(),
+        )
+        .bind(Utc::now().timestamp_micros())
+        .execute(pool)
+        .await?;
+    }
+
+    sqlx::query("UPDATE item_pic SET image_hash = $2, mirror_error = NULL WHERE url = $1;")
+        .bind(url)
+        .bind(&hash)
+        .execute(pool)
+        .await?;
+
+    Ok(known.is_none())
+}
+
+/// The stored thumbnail for a requested width, None if the image isn't mirrored.
+pub async fn load_thumbnail<S: ImageStorage>(
+    pool: &Pool<Postgres>,
+    storage: &S,
+    hash: &str,
+    requested_width: u32,
+    format: ThumbnailFormat,
+) -> Result<Option<Vec<u8>>> {
+    let widths: Option<(Vec<i32>,)> =
+        sqlx::query_as("SELECT thumbnail_widths FROM image WHERE hash = $1;")
+            .bind(hash)
+            .fetch_optional(pool)
+            .await?;
+    let Some((widths,)) = widths else {
+        return Ok(None);
+    };
+    let widths: Vec<u32> = widths.iter().map(|width| *width as u32).collect();
+    match pick_width(&widths, requested_width) {
+        Some(width) => storage.get(&thumbnail_key(hash, width, format)).await,
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Cursor, net::TcpListener};
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use anyhow::Result;
+    use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+
+    use crate::{
+        image_mirror::{
+            content_hash, load_thumbnail, mirror_urls, pick_width, process_image, thumbnail_key,
+            ImageStorage, MemoryStorage, MirrorConfig, MirrorSummary, ThumbnailFormat,
+        },
+        init_pool,
+    };
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = vec![];
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn makes_thumbnails_without_scaling_up() {
+        let processed = process_image(&png(800, 400), &MirrorConfig::default()).unwrap();
+        assert_eq!((processed.width, processed.height), (800, 400));
+        assert_eq!(processed.thumbnail_widths(), vec![320, 640, 800]);
+
+        let (_, _, small_jpeg) = processed
+            .thumbnails
+            .iter()
+            .find(|(width, format, _)| *width == 320 && *format == ThumbnailFormat::Jpeg)
+            .unwrap();
+        let small = image::load_from_memory(small_jpeg).unwrap();
+        assert_eq!((small.width(), small.height()), (320, 160));
+    }
+
+    #[test]
+    fn webp_thumbnails_are_lossy() {
+        // gradients with some noise, like a photo
+        let photo = RgbImage::from_fn(640, 480, |x, y| {
+            let noise = ((x * 7919 + y * 104729) % 17) as u8;
+            Rgb([(x / 3) as u8 + noise, (y / 2) as u8, ((x + y) / 5) as u8])
+        });
+        let mut bytes = vec![];
+        DynamicImage::ImageRgb8(photo)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+
+        let processed = process_image(&bytes, &MirrorConfig::default()).unwrap();
+        let size = |format: ThumbnailFormat| {
+            let (_, _, encoded) = processed
+                .thumbnails
+                .iter()
+                .find(|(width, f, _)| *width == 640 && *f == format)
+                .unwrap();
+            encoded.len()
+        };
+        assert!(size(ThumbnailFormat::Webp) < size(ThumbnailFormat::Jpeg));
+    }
+
+    #[test]
+    fn picks_smallest_sufficient_width() {
+        let widths = [320, 640, 800];
+        assert_eq!(pick_width(&widths, 390), Some(640));
+        assert_eq!(pick_width(&widths, 320), Some(320));
+        assert_eq!(pick_width(&widths, 1500), Some(800));
+        assert_eq!(pick_width(&[], 320), None);
+    }
+
+    #[tokio::test]
+    async fn mirrors_pictures_once() -> Result<()> {
+        // a unique image per run, so it isn't mirrored already
+        let image = png(
+            500,
+            10 + (chrono::Utc::now().timestamp_micros() % 1000) as u32,
+        );
+        let hash = content_hash(&image);
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let served = image.clone();
+        let server = HttpServer::new(move || {
+            let served = served.clone();
+            App::new()
+                .route(
+                    "/ok.png",
+                    web::get().to(move || {
+                        let served = served.clone();
+                        async move { HttpResponse::Ok().body(served) }
+                    }),
+                )
+                .route("/gone.png", web::get().to(HttpResponse::NotFound))
+        })
+        .listen(listener)?
+        .workers(1)
+        .run();
+        let server_handle = server.handle();
+        tokio::spawn(server);
+
+        let pool = init_pool("5433").await;
+        let mut item_ids = vec![];
+        for link in ["https://foo.bar/mirrored", "https://foo.bar/mirrored/2"] {
+            let (item_id,): (i32,) = sqlx::query_as(
+                r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
+VALUES ('mock product mirrored', 10, 'EUR', $1, 'mock', 1, '')
+ON CONFLICT (vendor_link) DO UPDATE SET descr = ''
+RETURNING id;
+"#,
+            )
+            .bind(link)
+            .fetch_one(&pool)
+            .await?;
+            item_ids.push(item_id);
+        }
+        sqlx::query("DELETE FROM item_pic WHERE item_id = ANY($1);")
+            .bind(&item_ids)
+            .execute(&pool)
+            .await?;
+        let ok_url = format!("http://127.0.0.1:{}/ok.png", port);
+        let gone_url = format!("http://127.0.0.1:{}/gone.png", port);
+        // the same url on two items is downloaded once
+        sqlx::query(
+            "INSERT INTO item_pic (item_id, url) SELECT * FROM UNNEST($1::INTEGER[], $2::TEXT[]);",
+        )
+        .bind(vec![item_ids[0], item_ids[1], item_ids[0]])
+        .bind(vec![&ok_url, &ok_url, &gone_url])
+        .execute(&pool)
+        .await?;
+
+        let storage = MemoryStorage::default();
+        let config = MirrorConfig::default();
+        let summary = mirror_urls(
+            &pool,
+            &storage,
+            &reqwest::Client::new(),
+            &config,
+            &[ok_url, gone_url],
+        )
+        .await?;
+        server_handle.stop(true).await;
+        assert_eq!(
+            summary,
+            MirrorSummary {
+                mirrored: 1,
+                reused: 0,
+                failed: 1
+            }
+        );
+
+        let pictures: Vec<(String, Option<String>, i32)> = sqlx::query_as(
+            "SELECT url, image_hash, mirror_attempts FROM item_pic WHERE item_id = ANY($1) ORDER BY id;",
+        )
+        .bind(&item_ids)
+        .fetch_all(&pool)
+        .await?;
+        assert_eq!(pictures[0].1.as_deref(), Some(hash.as_str()));
+        assert_eq!(pictures[1].1.as_deref(), Some(hash.as_str()));
+        assert_eq!((pictures[2].1.as_deref(), pictures[2].2), (None, 1));
+
+        assert!(storage
+            .get(&thumbnail_key(&hash, 320, ThumbnailFormat::Webp))
+            .await?
+            .is_some());
+        let served = load_thumbnail(&pool, &storage, &hash, 400, ThumbnailFormat::Jpeg)
+            .await?
+            .unwrap();
+        assert_eq!(image::load_from_memory(&served)?.width(), 500);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Result};
+use url::Url;
+
+/// How a srcset entry describes its size, see
+/// https://html.spec.whatwg.org/multipage/images.html#srcset-attributes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Descriptor {
+    // e.g. "1.5x"
+    Density(f64),
+    // e.g. "640w"
+    Width(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrcSetEntry {
+    pub url: String,
+    pub descriptor: Descriptor,
+}
+
+/// Parses the entries of a srcset attribute, an entry without descriptor is "1x".
+pub fn parse_srcset(srcset: &str) -> Result<Vec<SrcSetEntry>> {
+    let mut entries = vec![];
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+
+        // urls may contain commas, only a trailing one ends the entry
+        let url_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (url, after_url) = rest.split_at(url_end);
+        let (url, descriptor_text, after_entry) = match url.strip_suffix(',') {
+            Some(url) => (url.trim_end_matches(','), "", after_url),
+            None => {
+                let descriptor_end = after_url.find(',').unwrap_or(after_url.len());
+                let (descriptor, after_entry) = after_url.split_at(descriptor_end);
+                (url, descriptor.trim(), after_entry)
+            }
+        };
+        rest = after_entry;
+
+        entries.push(SrcSetEntry {
+            url: url.to_string(),
+            descriptor: parse_descriptor(descriptor_text)?,
+        });
+    }
+    Ok(entries)
+}
+
+fn parse_descriptor(text: &str) -> Result<Descriptor> {
+    let invalid = || anyhow!("invalid srcset descriptor: {}", text);
+    if text.is_empty() {
+        return Ok(Descriptor::Density(1.0));
+    }
+    if let Some(density) = text.strip_suffix('x') {
+        let density: f64 = density.parse().map_err(|_| invalid())?;
+        return if density > 0.0 {
+            Ok(Descriptor::Density(density))
+        } else {
+            Err(invalid())
+        };
+    }
+    if let Some(width) = text.strip_suffix('w') {
+        return width.parse().map(Descriptor::Width).map_err(|_| invalid());
+    }
+    Err(invalid())
+}
+
+/// The url of the largest image of a srcset, whatever the order of the entries.
+/// Width descriptors win over densities if a srcset mixes both, they're the more precise size.
+pub fn largest_src(srcset: &str) -> Result<String> {
+    let entries = parse_srcset(srcset)?;
+    let widest = entries
+        .iter()
+        .filter_map(|entry| match entry.descriptor {
+            Descriptor::Width(width) => Some((entry, width)),
+            Descriptor::Density(_) => None,
+        })
+        .max_by_key(|(_, width)| *width);
+    let densest = entries
+        .iter()
+        .filter_map(|entry| match entry.descriptor {
+            Descriptor::Density(density) => Some((entry, density)),
+            Descriptor::Width(_) => None,
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    widest
+        .map(|(entry, _)| entry)
+        .or(densest.map(|(entry, _)| entry))
+        .map(|entry| entry.url.clone())
+        .ok_or_else(|| anyhow!("srcset didn't contain any images"))
+}
+
+// widths images are requested in, so the cdn can cache them for all clients
+const WIDTH_STEPS: [u32; 8] = [160, 320, 480, 640, 800, 960, 1280, MAX_WIDTH];
+
+pub const MAX_WIDTH: u32 = 1500;
+
+/// The image width for a screen in css pixels and its device pixel ratio, rounded up to a step.
+pub fn target_width(screen_width: u32, dpr: f64) -> u32 {
+    let pixels = (screen_width as f64 * dpr.clamp(1.0, 4.0)).ceil() as u32;
+    WIDTH_STEPS
+        .iter()
+        .copied()
+        .find(|step| *step >= pixels)
+        .unwrap_or(WIDTH_STEPS[WIDTH_STEPS.len() - 1])
+}
+
+// size tokens of amazon image urls, e.g. UL320 (longest side), SX640 (width) or SY695 (height)
+const SIZE_TOKENS: [&str; 8] = ["UL", "SX", "SY", "UX", "UY", "SL", "SS", "US"];
+
+// the token sizing the same way by width, a height token would make the image that high instead
+fn width_token(token: &str) -> &str {
+    match token {
+        "SY" => "SX",
+        "UY" => "UX",
+        token => token,
+    }
+}
+
+fn is_amazon_image(url: &Url) -> bool {
+    url.host_str().is_some_and(|host| {
+        host.ends_with("media-amazon.com") || host.ends_with("images-amazon.com")
+    })
+}
+
+/// Rewrites the size token of an amazon image url, e.g. `._AC_UL960_FMwebp_QL65_.jpg` to
+/// `._AC_UL480_FMwebp_QL65_.jpg`, so the cdn serves a smaller image. Height tokens become width tokens,
+/// `._AC_SY695_.jpg` to `._AC_SX480_.jpg`. Other urls are returned as they are.
+pub fn resize_amazon_image(url: &str, width: u32) -> String {
+    let Ok(parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    if !is_amazon_image(&parsed) {
+        return url.to_string();
+    }
+
+    let path = parsed.path();
+    let file_start = path.rfind('/').map_or(0, |i| i + 1);
+    let (dir, file) = path.split_at(file_start);
+    let Some((name, extension)) = file.rsplit_once('.') else {
+        return url.to_string();
+    };
+
+    // "61mXk3rN0BL._AC_UL960_FMwebp_QL65_" -> id and modifiers
+    let resized = match name.split_once("._") {
+        Some((id, modifiers)) => {
+            let mut replaced = false;
+            let modifiers: Vec<String> = modifiers
+                .split('_')
+                .map(|modifier| {
+                    let token = SIZE_TOKENS.iter().find(|token| {
+                        modifier.strip_prefix(**token).is_some_and(|size| {
+                            !size.is_empty() && size.chars().all(|c| c.is_ascii_digit())
+                        })
+                    });
+                    match token {
+                        Some(token) if !replaced => {
+                            replaced = true;
+                            format!("{}{}", width_token(token), width)
+                        }
+                        _ => modifier.to_string(),
+                    }
+                })
+                .collect();
+            if replaced {
+                format!("{}._{}", id, modifiers.join("_"))
+            } else {
+                format!("{}._UL{}_{}", id, width, modifiers.join("_"))
+            }
+        }
+        None => format!("{}._AC_UL{}_", name, width),
+    };
+
+    let mut resized_url = parsed.clone();
+    resized_url.set_path(&format!("{}{}.{}", dir, resized, extension));
+    resized_url.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::images::{largest_src, parse_srcset, resize_amazon_image, target_width, Descriptor};
+
+    #[test]
+    fn picks_largest_regardless_of_order() {
+        let densities = "a.jpg 2x, b.jpg 3x, c.jpg 1.5x, d.jpg";
+        assert_eq!(largest_src(densities).unwrap(), "b.jpg");
+
+        let widths = "https://x.com/a,b.jpg 960w, https://x.com/c.jpg 320w";
+        assert_eq!(largest_src(widths).unwrap(), "https://x.com/a,b.jpg");
+
+        assert_eq!(
+            parse_srcset("a.jpg, b.jpg 640w").unwrap()[0].descriptor,
+            Descriptor::Density(1.0)
+        );
+        assert!(largest_src("").is_err());
+        assert!(largest_src("a.jpg 2y").is_err());
+    }
+
+    #[test]
+    fn rewrites_amazon_size_token() {
+        assert_eq!(
+            resize_amazon_image(
+                "https://m.media-amazon.com/images/I/61mXk3rN0BL._AC_UL960_FMwebp_QL65_.jpg",
+                480
+            ),
+            "https://m.media-amazon.com/images/I/61mXk3rN0BL._AC_UL480_FMwebp_QL65_.jpg"
+        );
+        assert_eq!(
+            resize_amazon_image(
+                "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_SY695_.jpg",
+                640
+            ),
+            "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_SX640_.jpg"
+        );
+        assert_eq!(
+            resize_amazon_image(
+                "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_UY436_FMwebp_.jpg",
+                320
+            ),
+            "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_UX320_FMwebp_.jpg"
+        );
+        // without size token
+        assert_eq!(
+            resize_amazon_image("https://m.media-amazon.com/images/I/41Qb7lO3kSL.jpg", 320),
+            "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_UL320_.jpg"
+        );
+        assert_eq!(
+            resize_amazon_image("https://doesntexist.com/foo._AC_UL960_.png", 320),
+            "https://doesntexist.com/foo._AC_UL960_.png"
+        );
+    }
+
+    #[test]
+    fn rounds_target_width_up_to_step() {
+        assert_eq!(target_width(390, 3.0), 1280);
+        assert_eq!(target_width(360, 2.0), 800);
+        assert_eq!(target_width(320, 1.0), 320);
+        assert_eq!(target_width(1440, 2.0), 1500);
+    }
+}
@@ -0,0 +1,714 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use bigdecimal::BigDecimal;
+use chrono::Utc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::{prelude::FromRow, types::Json, Pool, Postgres};
+
+use crate::images::{resize_amazon_image, MAX_WIDTH};
+
+// saved but not reviewed yet
+pub const STATUS_DRAFT: &str = "draft";
+// in the feed
+pub const STATUS_PUBLISHED: &str = "published";
+// taken out of the feed by us
+pub const STATUS_HIDDEN: &str = "hidden";
+// no longer sold by the vendor
+pub const STATUS_UNAVAILABLE: &str = "unavailable";
+pub const STATUS_REMOVED: &str = "removed";
+
+pub const ITEM_STATUSES: [&str; 5] = [
+    STATUS_DRAFT,
+    STATUS_PUBLISHED,
+    STATUS_HIDDEN,
+    STATUS_UNAVAILABLE,
+    STATUS_REMOVED,
+];
+
+#[derive(Debug, Default, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name_: String,
+    // formatted for the client's locale after loading
+    #[sqlx(skip)]
+    pub price: String,
+    #[serde(serialize_with = "crate::money::serialize_as_number")]
+    pub price_number: BigDecimal,
+    pub price_currency: String,
+    pub pictures: Vec<String>,
+    // the mirrored image of each picture, see image_mirror.rs
+    #[serde(skip)]
+    pub picture_hashes: Vec<Option<String>>,
+    pub vendor_link: String,
+    pub type_: String,
+    pub descr: String,
+    pub added_timestamp: i64,
+    pub marketplace: String,
+    pub brand: Option<String>,
+    pub material: Option<String>,
+    pub colour: Option<String>,
+    // stars out of 5
+    pub rating: Option<f64>,
+    pub review_count: Option<i32>,
+    pub prime: bool,
+    pub parent_asin: Option<String>,
+    // the variants of the parent, empty if the item has none or wasn't enriched yet
+    #[sqlx(skip)]
+    pub variants: Vec<ItemVariant>,
+    // one of ITEM_STATUSES, e.g. so wishlists can show items that aren't available anymore
+    pub status: String,
+    // a note for us, not for the clients
+    #[serde(skip)]
+    pub status_reason: Option<String>,
+    // none if the status didn't change since the item was saved
+    pub status_timestamp: Option<i64>,
+    // how likely the item belongs to its type, see relevance.rs
+    #[serde(skip)]
+    pub relevance_confidence: Option<f64>,
+}
+
+impl Item {
+    /// Points the pictures to images of the given width, see images::target_width.
+    /// Mirrored pictures are served by us at `public_base_url`, the largest thumbnail without a width.
+    /// The others stay on the amazon cdn, as saved (the largest) without a width.
+    pub fn resize_images(&mut self, width: Option<u32>, public_base_url: &str) {
+        for (picture, hash) in self.pictures.iter_mut().zip(&self.picture_hashes) {
+            match (hash, width) {
+                (Some(hash), _) => {
+                    *picture = format!(
+                        "{}/img/{}/{}",
+                        public_base_url.trim_end_matches('/'),
+                        hash,
+                        width.unwrap_or(MAX_WIDTH)
+                    );
+                }
+                (None, Some(width)) => *picture = resize_amazon_image(picture, width),
+                (None, None) => {}
+            }
+        }
+        if let Some(width) = width {
+            for variant in &mut self.variants {
+                variant.img = variant
+                    .img
+                    .as_deref()
+                    .map(|img| resize_amazon_image(img, width));
+            }
+        }
+    }
+}
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemVariant {
+    #[serde(skip)]
+    pub marketplace: String,
+    #[serde(skip)]
+    pub parent_asin: String,
+    pub asin: String,
+    // dimension -> value, e.g. {"color": "Silber", "size": "54"}
+    pub dimensions: Json<BTreeMap<String, String>>,
+    // formatted for the client's locale after loading
+    #[sqlx(skip)]
+    pub price: String,
+    #[serde(serialize_with = "crate::money::serialize_as_number")]
+    pub price_number: BigDecimal,
+    pub price_currency: String,
+    pub img: Option<String>,
+    pub vendor_link: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Filters {
+    pub type_: Vec<String>,
+    pub price: Vec<u32>,
+    // marketplace domains, the ones of the client's region if empty
+    #[serde(default)]
+    pub marketplace: Vec<String>,
+    // any of the brands, case insensitive
+    #[serde(default)]
+    pub brand: Vec<String>,
+    // stars out of 5, inclusive
+    #[serde(default)]
+    pub min_rating: Option<f64>,
+    // materials the item's material contains, e.g. "silber" matches "925 Sterling Silber"
+    #[serde(default)]
+    pub material: Vec<String>,
+    #[serde(default)]
+    pub colour: Vec<String>,
+    // only prime items if true
+    #[serde(default)]
+    pub prime: bool,
+}
+
+/// Empty lists and None don't filter.
+#[derive(Debug)]
+pub struct DbFilters {
+    // always filters, see to_db_filters for the types if the client didn't pick any
+    pub type_: Vec<String>,
+    // empty means all marketplaces
+    pub marketplace: Vec<String>,
+    // inclusive
+    pub price_min: BigDecimal,
+    // exclusive
+    pub price_max: BigDecimal,
+    // lowercase
+    pub brand: Vec<String>,
+    pub min_rating: Option<f64>,
+    // lowercase
+    pub material: Vec<String>,
+    // lowercase
+    pub colour: Vec<String>,
+    pub prime_only: bool,
+}
+
+pub fn decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).expect("invalid decimal literal")
+}
+
+// price filter buckets are half open ranges: [0, 20), [20, 50), [50, 100), [100, max)
+pub fn to_min_max(price_filter: &[u32]) -> PriceBounds {
+    let min_possible_price = decimal("0");
+    let max_possible_price = decimal("1000000");
+
+    if price_filter.is_empty() {
+        return PriceBounds {
+            min: min_possible_price,
+            max: max_possible_price,
+        };
+    }
+
+    let buckets = [
+        (1, decimal("0"), decimal("20")),
+        (2, decimal("20"), decimal("50")),
+        (3, decimal("50"), decimal("100")),
+        (4, decimal("100"), max_possible_price.clone()),
+    ];
+
+    let mut min = max_possible_price;
+    let mut max = min_possible_price;
+
+    for (bucket, bucket_min, bucket_max) in buckets {
+        if price_filter.contains(&bucket) {
+            min = min.min(bucket_min);
+            max = max.max(bucket_max);
+        }
+    }
+
+    PriceBounds { min, max }
+}
+
+pub struct PriceBounds {
+    pub min: BigDecimal,
+    pub max: BigDecimal,
+}
+
+/// `region_marketplaces` are used if the client didn't ask for specific marketplaces.
+pub fn to_db_filters(filters: &Filters, region_marketplaces: &[String]) -> DbFilters {
+    let type_filter = if filters.type_.is_empty() {
+        vec![
+            "necklace".to_string(),
+            "bracelet".to_string(),
+            "ring".to_string(),
+            "earring".to_string(),
+        ]
+    } else {
+        filters.type_.clone()
+    };
+
+    let price_bounds = to_min_max(&filters.price);
+
+    DbFilters {
+        type_: type_filter,
+        marketplace: if filters.marketplace.is_empty() {
+            region_marketplaces.to_vec()
+        } else {
+            filters.marketplace.clone()
+        },
+        price_min: price_bounds.min,
+        price_max: price_bounds.max,
+        brand: lowercase(&filters.brand),
+        min_rating: filters.min_rating,
+        material: lowercase(&filters.material),
+        colour: lowercase(&filters.colour),
+        prime_only: filters.prime,
+    }
+}
+
+fn lowercase(values: &[String]) -> Vec<String> {
+    values
+        .iter()
+        .map(|value| value.trim().to_lowercase())
+        .collect()
+}
+
+// the filters are written once in `visible`, which isn't materialized but inlined where it's used
+// items sharing a parent are variants, only the first saved one passing the filters is returned and its variants are attached
+// pictures are collected with a lateral subquery, which only runs for the returned page
+// the feed index (added_timestamp, type_, price_number, marketplace) lets the scan stop after the page is filled
+// pub so the benchmark can explain it
+pub const LOAD_ITEMS_QUERY: &str = r#"
+WITH visible AS NOT MATERIALIZED (
+    SELECT i.*
+    FROM item i
+    WHERE
+        i.status = 'published'
+        AND i.type_ = ANY($2) AND i.price_number >= $3 AND i.price_number < $4
+        AND (cardinality($5::TEXT[]) = 0 OR i.marketplace = ANY($5))
+        AND (cardinality($6::TEXT[]) = 0 OR lower(i.brand) = ANY($6))
+        AND ($7::FLOAT8 IS NULL OR i.rating >= $7)
+        AND (cardinality($8::TEXT[]) = 0 OR EXISTS (SELECT 1 FROM unnest($8::TEXT[]) m WHERE strpos(lower(i.material), m) > 0))
+        AND (cardinality($9::TEXT[]) = 0 OR EXISTS (SELECT 1 FROM unnest($9::TEXT[]) c WHERE strpos(lower(i.colour), c) > 0))
+        AND (NOT $10 OR i.prime)
+        -- items with pictures need one that isn't dead
+        AND (NOT EXISTS (SELECT 1 FROM item_pic ip WHERE ip.item_id = i.id) OR EXISTS (
+            SELECT 1 FROM item_pic ip
+            WHERE ip.item_id = i.id AND (ip.status IS DISTINCT FROM 'dead' OR ip.image_hash IS NOT NULL)
+        ))
+)
+SELECT
+    i.id::TEXT AS id,
+    i.name_,
+    i.price_number,
+    i.price_currency,
+    i.vendor_link,
+    i.type_,
+    i.descr,
+    i.added_timestamp,
+    i.marketplace,
+    i.brand,
+    i.material,
+    i.colour,
+    i.rating,
+    i.review_count,
+    i.prime,
+    i.parent_asin,
+    i.status,
+    i.status_reason,
+    i.status_timestamp,
+    i.relevance_confidence,
+    COALESCE(p.pictures, ARRAY[]::TEXT[]) AS pictures,
+    COALESCE(p.picture_hashes, ARRAY[]::TEXT[]) AS picture_hashes
+FROM
+    visible i
+LEFT JOIN LATERAL (
+    SELECT array_agg(ip.url ORDER BY ip.position, ip.id) AS pictures,
+        array_agg(ip.image_hash ORDER BY ip.position, ip.id) AS picture_hashes
+    FROM item_pic ip
+    WHERE ip.item_id = i.id AND (ip.status IS DISTINCT FROM 'dead' OR ip.image_hash IS NOT NULL)
+) p ON TRUE
+WHERE
+    i.added_timestamp > $1
+    AND (i.parent_asin IS NULL OR NOT EXISTS (
+        SELECT 1 FROM visible s
+        WHERE s.parent_asin = i.parent_asin AND s.marketplace = i.marketplace AND s.added_timestamp < i.added_timestamp
+    ))
+ORDER BY i.added_timestamp
+LIMIT 50;
+"#;
+
+// TODO redunancy filters price-filters
+pub async fn load_items(
+    pool: &Pool<Postgres>,
+    after_timestamp: i64,
+    filters: &DbFilters,
+) -> Result<Vec<Item>> {
+    info!("filters: {:?}", filters);
+
+    let mut res: Vec<Item> = sqlx::query_as(LOAD_ITEMS_QUERY)
+        .bind(after_timestamp)
+        .bind(filters.type_.clone())
+        .bind(&filters.price_min)
+        .bind(&filters.price_max)
+        .bind(&filters.marketplace)
+        .bind(&filters.brand)
+        .bind(filters.min_rating)
+        .bind(&filters.material)
+        .bind(&filters.colour)
+        .bind(filters.prime_only)
+        .fetch_all(pool)
+        .await?;
+
+    attach_variants(pool, &mut res).await?;
+
+    Ok(res)
+}
+
+/// Whether any of the marketplaces has items in the feed.
+pub async fn has_published_items(pool: &Pool<Postgres>, marketplaces: &[String]) -> Result<bool> {
+    let (exists,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM item WHERE status = 'published' AND marketplace = ANY($1));",
+    )
+    .bind(marketplaces)
+    .fetch_one(pool)
+    .await?;
+    Ok(exists)
+}
+
+/// An item by id whatever its status, none if there's no such item.
+pub async fn load_item(pool: &Pool<Postgres>, id: i32) -> Result<Option<Item>> {
+    let item: Option<Item> = sqlx::query_as(
+        r#"
+SELECT
+    i.id::TEXT AS id,
+    i.name_,
+    i.price_number,
+    i.price_currency,
+    i.vendor_link,
+    i.type_,
+    i.descr,
+    i.added_timestamp,
+    i.marketplace,
+    i.brand,
+    i.material,
+    i.colour,
+    i.rating,
+    i.review_count,
+    i.prime,
+    i.parent_asin,
+    i.status,
+    i.status_reason,
+    i.status_timestamp,
+    i.relevance_confidence,
+    COALESCE(p.pictures, ARRAY[]::TEXT[]) AS pictures,
+    COALESCE(p.picture_hashes, ARRAY[]::TEXT[]) AS picture_hashes
+FROM
+    item i
+LEFT JOIN LATERAL (
+    SELECT array_agg(ip.url ORDER BY ip.position, ip.id) AS pictures,
+        array_agg(ip.image_hash ORDER BY ip.position, ip.id) AS picture_hashes
+    FROM item_pic ip
+    WHERE ip.item_id = i.id AND (ip.status IS DISTINCT FROM 'dead' OR ip.image_hash IS NOT NULL)
+) p ON TRUE
+WHERE i.id = $1;
+"#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(item) = item else {
+        return Ok(None);
+    };
+    let mut items = [item];
+    attach_variants(pool, &mut items).await?;
+    let [item] = items;
+    Ok(Some(item))
+}
+
+/// The drafts to be reviewed, the least likely to belong to their type first.
+/// An item leaves the queue when it's published or removed, see set_item_status.
+pub async fn load_review_queue(pool: &Pool<Postgres>, limit: i64) -> Result<Vec<Item>> {
+    let mut items: Vec<Item> = sqlx::query_as(
+        r#"
+SELECT
+    i.id::TEXT AS id,
+    i.name_,
+    i.price_number,
+    i.price_currency,
+    i.vendor_link,
+    i.type_,
+    i.descr,
+    i.added_timestamp,
+    i.marketplace,
+    i.brand,
+    i.material,
+    i.colour,
+    i.rating,
+    i.review_count,
+    i.prime,
+    i.parent_asin,
+    i.status,
+    i.status_reason,
+    i.status_timestamp,
+    i.relevance_confidence,
+    COALESCE(p.pictures, ARRAY[]::TEXT[]) AS pictures,
+    COALESCE(p.picture_hashes, ARRAY[]::TEXT[]) AS picture_hashes
+FROM
+    item i
+LEFT JOIN LATERAL (
+    SELECT array_agg(ip.url ORDER BY ip.position, ip.id) AS pictures,
+        array_agg(ip.image_hash ORDER BY ip.position, ip.id) AS picture_hashes
+    FROM item_pic ip
+    WHERE ip.item_id = i.id
+) p ON TRUE
+WHERE i.status = 'draft'
+ORDER BY i.relevance_confidence NULLS FIRST, i.id
+LIMIT $1;
+"#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    attach_variants(pool, &mut items).await?;
+    Ok(items)
+}
+
+/// Moves an item to another status of ITEM_STATUSES, returns false if there's no such item.
+/// Items are never deleted, their pictures and the wishlists referencing them stay valid.
+pub async fn set_item_status(
+    pool: &Pool<Postgres>,
+    id: i32,
+    status: &str,
+    reason: Option<&str>,
+) -> Result<bool> {
+    if !ITEM_STATUSES.contains(&status) {
+        return Err(anyhow!("unknown item status: {}", status));
+    }
+    let res = sqlx::query(
+        "UPDATE item SET status = $2, status_reason = $3, status_timestamp = $4 WHERE id = $1;",
+    )
+    .bind(id)
+    .bind(status)
+    .bind(reason)
+    .bind(Utc::now().timestamp_micros())
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+async fn attach_variants(pool: &Pool<Postgres>, items: &mut [Item]) -> Result<()> {
+    let parent_asins: Vec<String> = items
+        .iter()
+        .filter_map(|item| item.parent_asin.clone())
+        .collect();
+    if parent_asins.is_empty() {
+        return Ok(());
+    }
+
+    let variants: Vec<ItemVariant> = sqlx::query_as(
+        r#"
+SELECT marketplace, parent_asin, asin, dimensions, price_number, price_currency, img, vendor_link
+FROM item_variant
+WHERE parent_asin = ANY($1)
+ORDER BY id;
+"#,
+    )
+    .bind(&parent_asins)
+    .fetch_all(pool)
+    .await?;
+
+    for variant in variants {
+        if let Some(item) = items.iter_mut().find(|item| {
+            item.parent_asin.as_ref() == Some(&variant.parent_asin)
+                && item.marketplace == variant.marketplace
+        }) {
+            item.variants.push(variant);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use sqlx::{Pool, Postgres};
+
+    use crate::{
+        init_pool,
+        items::{
+            decimal, load_item, load_items, load_review_queue, set_item_status, to_db_filters,
+            to_min_max, Filters, Item, STATUS_DRAFT, STATUS_HIDDEN, STATUS_PUBLISHED,
+            STATUS_UNAVAILABLE,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_load_items_after_timestamp() {
+        let pool = init_pool("5432").await;
+
+        let filters = Filters {
+            type_: vec!["necklace".to_string(), "bracelet".to_string()],
+            price: vec![1, 2, 3, 4],
+            ..Default::default()
+        };
+
+        let items = load_items(&pool, 0, &to_db_filters(&filters, &[]))
+            .await
+            .unwrap();
+        println!("loaded all items len: {}", items.len());
+
+        let items = load_items(&pool, 1739368334742824, &to_db_filters(&filters, &[]))
+            .await
+            .unwrap();
+        println!("loaded  items after timestamp len: {}", items.len());
+    }
+
+    #[test]
+    fn requested_marketplaces_override_region() {
+        let region = vec!["amazon.de".to_string()];
+        let mut filters = Filters::default();
+        assert_eq!(to_db_filters(&filters, &region).marketplace, region);
+
+        filters.marketplace = vec!["amazon.co.uk".to_string()];
+        assert_eq!(
+            to_db_filters(&filters, &region).marketplace,
+            vec!["amazon.co.uk".to_string()]
+        );
+    }
+
+    #[test]
+    fn serves_mirrored_pictures_at_absolute_urls() {
+        let mut item = Item {
+            pictures: vec![
+                "https://m.media-amazon.com/images/I/61mXk3rN0BL._AC_UL960_.jpg".to_string(),
+                "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_UL960_.jpg".to_string(),
+            ],
+            picture_hashes: vec![Some("ab".repeat(32)), None],
+            ..Default::default()
+        };
+        item.resize_images(Some(480), "https://api.example.com/");
+        assert_eq!(
+            item.pictures,
+            vec![
+                format!("https://api.example.com/img/{}/480", "ab".repeat(32)),
+                "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_UL480_.jpg".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn price_buckets_are_half_open() {
+        let first = to_min_max(&[1]);
+        assert!(decimal("19.99") >= first.min && decimal("19.99") < first.max);
+        assert!(decimal("20") >= first.max);
+
+        let middle = to_min_max(&[2, 3]);
+        assert_eq!(middle.min, decimal("20"));
+        assert_eq!(middle.max, decimal("100"));
+    }
+
+    #[tokio::test]
+    async fn hidden_items_stay_resolvable_by_id() -> Result<()> {
+        let pool = init_pool("5433").await;
+        let (id,): (i32,) = sqlx::query_as(
+            r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
+VALUES ('mock product with status', 10, 'EUR', 'https://foo.bar/status', 'mock-status', 1, '')
+ON CONFLICT (vendor_link) DO UPDATE SET status = 'published'
+RETURNING id;
+"#,
+        )
+        .fetch_one(&pool)
+        .await?;
+
+        let filters = Filters {
+            type_: vec!["mock-status".to_string()],
+            ..Default::default()
+        };
+        let db_filters = to_db_filters(&filters, &[]);
+        assert_eq!(load_items(&pool, 0, &db_filters).await?.len(), 1);
+
+        assert!(set_item_status(&pool, id, STATUS_HIDDEN, Some("duplicate")).await?);
+        assert!(load_items(&pool, 0, &db_filters).await?.is_empty());
+        let item = load_item(&pool, id).await?.unwrap();
+        assert_eq!(item.status, STATUS_HIDDEN);
+        assert_eq!(item.status_reason.as_deref(), Some("duplicate"));
+        assert!(item.status_timestamp.is_some());
+
+        // drafts are in the review queue
+        assert!(set_item_status(&pool, id, STATUS_DRAFT, None).await?);
+        let queue = load_review_queue(&pool, 10_000).await?;
+        assert!(queue.iter().any(|item| item.id == id.to_string()));
+
+        assert!(set_item_status(&pool, id, "deleted", None).await.is_err());
+        assert!(!set_item_status(&pool, -1, STATUS_PUBLISHED, None).await?);
+        assert!(load_item(&pool, -1).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn filters_material_and_colour_literally() -> Result<()> {
+        let pool = init_pool("5433").await;
+        sqlx::query(
+            r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr, material, colour)
+VALUES ('mock product material', 10, 'EUR', 'https://foo.bar/material', 'mock-material', 1, '', '925 Sterling Silber', 'Rosé')
+ON CONFLICT (vendor_link) DO UPDATE SET status = 'published';
+"#,
+        )
+        .execute(&pool)
+        .await?;
+
+        let count = |material: &str, colour: &str| {
+            let filters = Filters {
+                type_: vec!["mock-material".to_string()],
+                material: vec![material.to_string()],
+                colour: vec![colour.to_string()],
+                ..Default::default()
+            };
+            let pool = &pool;
+            async move {
+                load_items(pool, 0, &to_db_filters(&filters, &[]))
+                    .await
+                    .unwrap()
+                    .len()
+            }
+        };
+        assert_eq!(count("Silber", "rosé").await, 1);
+        // wildcards of LIKE are matched as they are
+        assert_eq!(count("%", "rosé").await, 0);
+        assert_eq!(count("silber", "_").await, 0);
+
+        Ok(())
+    }
+
+    async fn insert_group_item(
+        pool: &Pool<Postgres>,
+        link: &str,
+        price: i32,
+        added: i64,
+    ) -> Result<i32> {
+        let (id,): (i32,) = sqlx::query_as(
+            r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr, parent_asin)
+VALUES ('mock product in group', $2, 'EUR', $1, 'mock-group', $3, '', 'MOCKGROUP')
+ON CONFLICT (vendor_link) DO UPDATE SET price_number = $2, status = 'published'
+RETURNING id;
+"#,
+        )
+        .bind(link)
+        .bind(price)
+        .bind(added)
+        .fetch_one(pool)
+        .await?;
+        Ok(id)
+    }
+
+    #[tokio::test]
+    async fn serves_group_when_first_variant_is_filtered_out() -> Result<()> {
+        let pool = init_pool("5433").await;
+        let first = insert_group_item(&pool, "https://foo.bar/group/a", 150, 1).await?;
+        let second = insert_group_item(&pool, "https://foo.bar/group/b", 10, 2).await?;
+
+        let load_ids = |price: Vec<u32>| {
+            let filters = Filters {
+                type_: vec!["mock-group".to_string()],
+                price,
+                ..Default::default()
+            };
+            let pool = &pool;
+            async move {
+                let items = load_items(pool, 0, &to_db_filters(&filters, &[]))
+                    .await
+                    .unwrap();
+                items.into_iter().map(|item| item.id).collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(load_ids(vec![]).await, vec![first.to_string()]);
+        // the first variant is outside the price filter
+        assert_eq!(load_ids(vec![1]).await, vec![second.to_string()]);
+
+        // the first variant isn't available anymore
+        set_item_status(&pool, first, STATUS_UNAVAILABLE, None).await?;
+        assert_eq!(load_ids(vec![]).await, vec![second.to_string()]);
+
+        Ok(())
+    }
+}
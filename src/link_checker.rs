@@ -0,0 +1,449 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use chrono::Utc;
+use log::{debug, error, info};
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use sqlx::{prelude::FromRow, Pool, Postgres};
+use tokio::time::Instant;
+use url::Url;
+
+use crate::items::{set_item_status, STATUS_PUBLISHED, STATUS_UNAVAILABLE};
+
+pub const STATUS_OK: &str = "ok";
+// gone for good, the item becomes unavailable and the picture is hidden
+pub const STATUS_DEAD: &str = "dead";
+// timeouts, throttling, server errors, checked again next time
+pub const STATUS_ERROR: &str = "error";
+
+/// Whether a response means the url is gone. Only a not found is, anything else may pass.
+pub fn status_for_response(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => STATUS_DEAD,
+        status if status.is_success() => STATUS_OK,
+        _ => STATUS_ERROR,
+    }
+}
+
+/// Waits between requests to the same host, so the checks don't get us throttled.
+pub struct RateLimiter {
+    min_interval: Duration,
+    last_requests: HashMap<String, Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(min_interval: Duration) -> RateLimiter {
+        RateLimiter {
+            min_interval,
+            last_requests: HashMap::new(),
+        }
+    }
+
+    pub async fn wait(&mut self, url: &str) {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default();
+        if let Some(last) = self.last_requests.get(&host) {
+            tokio::time::sleep_until(*last + self.min_interval).await;
+        }
+        self.last_requests.insert(host, Instant::now());
+    }
+}
+
+pub struct CheckConfig {
+    // between requests to the same host
+    pub min_interval: Duration,
+    // items checked less than this long ago are skipped
+    pub recheck_after: Duration,
+    pub timeout: Duration,
+    // items per batch
+    pub batch_size: i64,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        CheckConfig {
+            min_interval: Duration::from_secs(2),
+            recheck_after: Duration::from_secs(7 * 24 * 60 * 60),
+            timeout: Duration::from_secs(15),
+            batch_size: 100,
+        }
+    }
+}
+
+async fn check_url(
+    client: &reqwest::Client,
+    limiter: &mut RateLimiter,
+    config: &CheckConfig,
+    url: &str,
+) -> &'static str {
+    // head is enough for most servers, some only allow get
+    for method in [Method::HEAD, Method::GET] {
+        limiter.wait(url).await;
+        match client
+            .request(method.clone(), url)
+            .timeout(config.timeout)
+            .send()
+            .await
+        {
+            Ok(res)
+                if method == Method::HEAD
+                    && matches!(
+                        res.status(),
+                        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+                    ) =>
+            {
+                continue
+            }
+            Ok(res) => return status_for_response(res.status()),
+            Err(e) => {
+                debug!("error checking {}: {}", url, e);
+                return STATUS_ERROR;
+            }
+        }
+    }
+    STATUS_ERROR
+}
+
+// the status_reason of items made unavailable by a dead link, they're published again if it comes back
+pub const DEAD_LINK_REASON: &str = "vendor link is gone";
+
+#[derive(Debug, FromRow)]
+pub struct ItemToCheck {
+    pub id: i32,
+    pub vendor_link: String,
+    // see items::ITEM_STATUSES
+    pub status: String,
+    pub status_reason: Option<String>,
+}
+
+/// Items never checked first, then the ones checked longest ago.
+pub async fn load_items_to_check(
+    pool: &Pool<Postgres>,
+    config: &CheckConfig,
+) -> Result<Vec<ItemToCheck>> {
+    let checked_before = Utc::now().timestamp_micros() - config.recheck_after.as_micros() as i64;
+    let items = sqlx::query_as::<_, ItemToCheck>(
+        r#"
+SELECT id, vendor_link, status, status_reason
+FROM item
+WHERE link_checked_timestamp IS NULL OR link_checked_timestamp < $1
+ORDER BY link_checked_timestamp NULLS FIRST, id
+LIMIT $2;
+"#,
+    )
+    .bind(checked_before)
+    .bind(config.batch_size)
+    .fetch_all(pool)
+    .await?;
+    Ok(items)
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct CheckSummary {
+    pub links_dead: u32,
+    pub pictures_dead: u32,
+    // checks that didn't tell, retried next time
+    pub errors: u32,
+    pub checked: u32,
+}
+
+/// Checks the vendor link and the pictures of each item and records their status.
+/// Published items whose link is dead become unavailable, so wishlists show them as no longer available.
+pub async fn check_items(
+    pool: &Pool<Postgres>,
+    client: &reqwest::Client,
+    limiter: &mut RateLimiter,
+    config: &CheckConfig,
+    items: &[ItemToCheck],
+) -> Result<CheckSummary> {
+    let mut summary = CheckSummary::default();
+
+    for item in items {
+        let link_status = check_url(client, limiter, config, &item.vendor_link).await;
+        match link_status {
+            STATUS_DEAD => summary.links_dead += 1,
+            STATUS_ERROR => summary.errors += 1,
+            _ => {}
+        }
+        let now = Utc::now().timestamp_micros();
+        sqlx::query("UPDATE item SET link_status = $2, link_checked_timestamp = $3 WHERE id = $1;")
+            .bind(item.id)
+            .bind(link_status)
+            .bind(now)
+            .execute(pool)
+            .await?;
+        let made_unavailable = item.status_reason.as_deref() == Some(DEAD_LINK_REASON);
+        match (link_status, item.status.as_str()) {
+            (STATUS_DEAD, STATUS_PUBLISHED) => {
+                set_item_status(pool, item.id, STATUS_UNAVAILABLE, Some(DEAD_LINK_REASON)).await?;
+            }
+            (STATUS_OK, STATUS_UNAVAILABLE) if made_unavailable => {
+                set_item_status(pool, item.id, STATUS_PUBLISHED, None).await?;
+            }
+            _ => {}
+        }
+
+        let pictures: Vec<(i32, String)> =
+            sqlx::query_as("SELECT id, url FROM item_pic WHERE item_id = $1 ORDER BY id;")
+                .bind(item.id)
+                .fetch_all(pool)
+                .await?;
+        for (picture_id, url) in pictures {
+            let status = check_url(client, limiter, config, &url).await;
+            match status {
+                STATUS_DEAD => summary.pictures_dead += 1,
+                STATUS_ERROR => summary.errors += 1,
+                _ => {}
+            }
+            sqlx::query("UPDATE item_pic SET status = $2, checked_timestamp = $3 WHERE id = $1;")
+                .bind(picture_id)
+                .bind(status)
+                .bind(Utc::now().timestamp_micros())
+                .execute(pool)
+                .await?;
+        }
+
+        summary.checked += 1;
+    }
+
+    Ok(summary)
+}
+
+/// Periodically checks a batch of items. Meant to be spawned next to the server.
+pub async fn run_check_loop(pool: Pool<Postgres>, config: CheckConfig, interval: Duration) {
+    let client = reqwest::Client::new();
+    let mut limiter = RateLimiter::new(config.min_interval);
+    loop {
+        let result = match load_items_to_check(&pool, &config).await {
+            Ok(items) => check_items(&pool, &client, &mut limiter, &config, &items).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(summary) => {
+                if summary != CheckSummary::default() {
+                    info!("link check: {:?}", summary);
+                }
+            }
+            Err(e) => error!("error checking links: {}", e),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// How many items and pictures have each status, for the admin api.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckCounts {
+    // status -> count, "unchecked" for the ones not checked yet
+    pub links: HashMap<String, i64>,
+    pub pictures: HashMap<String, i64>,
+    // items hidden from the feed for a dead link or only dead pictures
+    pub hidden_items: i64,
+}
+
+pub async fn load_check_counts(pool: &Pool<Postgres>) -> Result<CheckCounts> {
+    let links: Vec<(String, i64)> =
+        sqlx::query_as("SELECT COALESCE(link_status, 'unchecked'), COUNT(*) FROM item GROUP BY 1;")
+            .fetch_all(pool)
+            .await?;
+    let pictures: Vec<(String, i64)> =
+        sqlx::query_as("SELECT COALESCE(status, 'unchecked'), COUNT(*) FROM item_pic GROUP BY 1;")
+            .fetch_all(pool)
+            .await?;
+    let (hidden_items,): (i64,) = sqlx::query_as(
+        r#"
+SELECT COUNT(*) FROM item i
+WHERE i.link_status = 'dead' OR (
+    EXISTS (SELECT 1 FROM item_pic ip WHERE ip.item_id = i.id)
+    AND NOT EXISTS (
+        SELECT 1 FROM item_pic ip
+        WHERE ip.item_id = i.id AND (ip.status IS DISTINCT FROM 'dead' OR ip.image_hash IS NOT NULL)
+    )
+);
+"#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(CheckCounts {
+        links: links.into_iter().collect(),
+        pictures: pictures.into_iter().collect(),
+        hidden_items,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::TcpListener, time::Duration};
+
+    use actix_web::{http::Method, web, App, HttpRequest, HttpResponse, HttpServer};
+    use anyhow::Result;
+    use reqwest::StatusCode;
+    use sqlx::{Pool, Postgres};
+    use tokio::time::Instant;
+
+    use crate::{
+        init_pool,
+        items::{
+            load_item, load_items, to_db_filters, Filters, STATUS_PUBLISHED, STATUS_UNAVAILABLE,
+        },
+        link_checker::{
+            check_items, status_for_response, CheckConfig, ItemToCheck, RateLimiter,
+            DEAD_LINK_REASON, STATUS_DEAD, STATUS_ERROR, STATUS_OK,
+        },
+    };
+
+    #[test]
+    fn only_not_found_is_dead() {
+        assert_eq!(status_for_response(StatusCode::OK), STATUS_OK);
+        assert_eq!(status_for_response(StatusCode::NOT_FOUND), STATUS_DEAD);
+        assert_eq!(status_for_response(StatusCode::GONE), STATUS_DEAD);
+        assert_eq!(
+            status_for_response(StatusCode::SERVICE_UNAVAILABLE),
+            STATUS_ERROR
+        );
+        assert_eq!(
+            status_for_response(StatusCode::TOO_MANY_REQUESTS),
+            STATUS_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn waits_between_requests_to_same_host() {
+        let mut limiter = RateLimiter::new(Duration::from_millis(50));
+        let start = Instant::now();
+        limiter.wait("http://a.com/1").await;
+        limiter.wait("http://b.com/1").await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        limiter.wait("http://a.com/2").await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    // ok, gone, head-not-allowed (only get works), like the marketplaces' and cdns' servers
+    fn start_stub_server() -> Result<(u16, actix_web::dev::ServerHandle)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/ok", web::route().to(HttpResponse::Ok))
+                .route("/gone", web::route().to(HttpResponse::NotFound))
+                .route(
+                    "/get-only",
+                    web::route().to(|req: HttpRequest| async move {
+                        if req.method() == Method::HEAD {
+                            HttpResponse::MethodNotAllowed().finish()
+                        } else {
+                            HttpResponse::Ok().finish()
+                        }
+                    }),
+                )
+        })
+        .listen(listener)?
+        .workers(1)
+        .run();
+        let handle = server.handle();
+        tokio::spawn(server);
+        Ok((port, handle))
+    }
+
+    async fn insert_mock_item(
+        pool: &Pool<Postgres>,
+        link: &str,
+        pictures: &[String],
+    ) -> Result<ItemToCheck> {
+        let (id,): (i32,) = sqlx::query_as(
+            r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
+VALUES ('mock product checked', 10, 'EUR', $1, 'mock-checked', 1, '')
+RETURNING id;
+"#,
+        )
+        .bind(link)
+        .fetch_one(pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO item_pic (item_id, url) SELECT $1, url FROM UNNEST($2::TEXT[]) AS t(url);",
+        )
+        .bind(id)
+        .bind(pictures)
+        .execute(pool)
+        .await?;
+        Ok(ItemToCheck {
+            id,
+            vendor_link: link.to_string(),
+            status: STATUS_PUBLISHED.to_string(),
+            status_reason: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn hides_items_with_dead_links_or_pictures() -> Result<()> {
+        let (port, server) = start_stub_server()?;
+        let url = |path: &str| format!("http://127.0.0.1:{}{}", port, path);
+        // the item links are unique, the stub server ignores the query
+        let link = |path: &str, name: &str| format!("{}?item={}", url(path), name);
+
+        let pool = init_pool("5433").await;
+        // the stub server's port differs between runs, so do the links of earlier runs' items
+        sqlx::query(
+            "DELETE FROM item_pic WHERE item_id IN (SELECT id FROM item WHERE type_ = 'mock-checked');",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("DELETE FROM item WHERE type_ = 'mock-checked';")
+            .execute(&pool)
+            .await?;
+
+        let items = vec![
+            insert_mock_item(&pool, &link("/ok", "alive"), &[url("/ok"), url("/gone")]).await?,
+            insert_mock_item(&pool, &link("/get-only", "get-only"), &[url("/ok")]).await?,
+            insert_mock_item(&pool, &link("/gone", "delisted"), &[url("/ok")]).await?,
+            insert_mock_item(&pool, &link("/ok", "no-pictures"), &[url("/gone")]).await?,
+        ];
+
+        let config = CheckConfig {
+            min_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut limiter = RateLimiter::new(config.min_interval);
+        let summary = check_items(
+            &pool,
+            &reqwest::Client::new(),
+            &mut limiter,
+            &config,
+            &items,
+        )
+        .await?;
+        server.stop(true).await;
+        assert_eq!(summary.checked, 4);
+        assert_eq!(summary.links_dead, 1);
+        assert_eq!(summary.pictures_dead, 2);
+        assert_eq!(summary.errors, 0);
+
+        let filters = Filters {
+            type_: vec!["mock-checked".to_string()],
+            ..Default::default()
+        };
+        let shown = load_items(&pool, 0, &to_db_filters(&filters, &[])).await?;
+        let mut shown_ids: Vec<String> = shown.iter().map(|item| item.id.clone()).collect();
+        shown_ids.sort();
+        let mut expected = vec![items[0].id.to_string(), items[1].id.to_string()];
+        expected.sort();
+        assert_eq!(shown_ids, expected);
+
+        // the dead picture isn't served
+        let alive = shown.iter().find(|item| item.id == items[0].id.to_string());
+        assert_eq!(alive.unwrap().pictures, vec![url("/ok")]);
+
+        // wishlists show the delisted item as no longer available
+        let delisted = load_item(&pool, items[2].id).await?.unwrap();
+        assert_eq!(delisted.status, STATUS_UNAVAILABLE);
+        assert_eq!(delisted.status_reason.as_deref(), Some(DEAD_LINK_REASON));
+        assert!(delisted.status_timestamp.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use url::Url;
+
+use crate::money::Locale;
+
+/// Where the items are from if nothing else is known, e.g. the scrape targets without a marketplace.
+pub const DEFAULT_MARKETPLACE: &str = "amazon.de";
+
+/// The supported marketplaces, read from a toml file (see marketplaces.toml).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Marketplaces {
+    #[serde(rename = "marketplace", default)]
+    pub marketplaces: Vec<Marketplace>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Marketplace {
+    /// e.g. "amazon.de", the site is https://www.amazon.de
+    pub domain: String,
+    pub locale: Locale,
+    /// ISO 4217 code of the prices
+    pub currency: String,
+    /// Associates tag added to the product links
+    pub affiliate_tag: Option<String>,
+    /// Name of the profile in selectors.toml
+    pub selector_profile: String,
+    /// Reject buttons of the cookie consent dialog, tried in order
+    #[serde(default)]
+    pub cookie_consent: Vec<String>,
+}
+
+impl Marketplace {
+    pub fn base_url(&self) -> String {
+        format!("https://www.{}", self.domain)
+    }
+}
+
+impl Marketplaces {
+    pub fn from_file(path: &Path) -> Result<Marketplaces> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("error reading {}: {}", path.display(), e))?;
+        Marketplaces::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Marketplaces> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn get(&self, domain: &str) -> Result<&Marketplace> {
+        self.marketplaces
+            .iter()
+            .find(|marketplace| marketplace.domain == domain)
+            .ok_or_else(|| anyhow!("unknown marketplace: {}", domain))
+    }
+
+    /// The marketplace a url belongs to, e.g. amazon.de for https://www.amazon.de/s?k=ringe.
+    pub fn for_url(&self, url: &str) -> Result<&Marketplace> {
+        let url = Url::parse(url)?;
+        let host = url.host_str().unwrap_or_default();
+        self.get(host.strip_prefix("www.").unwrap_or(host))
+    }
+
+    /// The domains of the marketplaces for users of a locale.
+    pub fn domains_for_locale(&self, locale: Locale) -> Vec<String> {
+        self.marketplaces
+            .iter()
+            .filter(|marketplace| marketplace.locale == locale)
+            .map(|marketplace| marketplace.domain.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{marketplace::Marketplaces, money::Locale};
+
+    #[test]
+    fn finds_marketplaces_by_url_and_locale() {
+        let marketplaces = Marketplaces::parse(include_str!("../marketplaces.toml")).unwrap();
+
+        let uk = marketplaces
+            .for_url("https://www.amazon.co.uk/s?k=rings")
+            .unwrap();
+        assert_eq!(uk.domain, "amazon.co.uk");
+        assert_eq!(uk.locale, Locale::Uk);
+        assert_eq!(uk.currency, "GBP");
+
+        assert!(marketplaces.for_url("https://www.ebay.de/").is_err());
+        assert_eq!(
+            marketplaces.domains_for_locale(Locale::Fr),
+            vec!["amazon.fr"]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive};
+use serde::{Deserialize, Serializer};
+
+/// Locales we format prices for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    De,
+    Fr,
+    It,
+    Es,
+    Uk,
+    Us,
+}
+
+impl Locale {
+    /// Maps a language tag like "de-DE" or "en-GB" to a locale, falling back to German (our main market).
+    pub fn from_language_tag(tag: &str) -> Locale {
+        let tag = tag.trim().to_lowercase();
+        let mut parts = tag.split(['-', '_']);
+        let language = parts.next().unwrap_or_default();
+        let region = parts.next().unwrap_or_default();
+
+        match (language, region) {
+            ("fr", _) => Locale::Fr,
+            ("it", _) => Locale::It,
+            ("es", _) => Locale::Es,
+            ("en", "gb") | ("en", "uk") => Locale::Uk,
+            ("en", _) => Locale::Us,
+            _ => Locale::De,
+        }
+    }
+
+    /// The locale of the first entry of an `Accept-Language` header, e.g. "fr-CH, fr;q=0.9, en;q=0.8".
+    pub fn from_accept_language(header: &str) -> Locale {
+        let first = header
+            .split(',')
+            .next()
+            .and_then(|entry| entry.split(';').next())
+            .unwrap_or_default();
+        Locale::from_language_tag(first)
+    }
+}
+
+fn currency_symbol(currency: &str) -> &str {
+    match currency {
+        "EUR" => "€",
+        "GBP" => "£",
+        "USD" => "$",
+        _ => currency,
+    }
+}
+
+/// Formats an amount in the given ISO 4217 currency for display, e.g. "1.299,99 €" for German.
+pub fn format_price(amount: &BigDecimal, currency: &str, locale: Locale) -> String {
+    let rounded = amount
+        .with_scale_round(2, RoundingMode::HalfEven)
+        .to_string();
+    let (sign, digits) = match rounded.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", rounded.as_str()),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, "00"));
+
+    let (thousands_separator, decimal_separator) = match locale {
+        Locale::De | Locale::It | Locale::Es => (".", ","),
+        Locale::Fr => ("\u{202f}", ","),
+        Locale::Uk | Locale::Us => (",", "."),
+    };
+
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push_str(thousands_separator);
+        }
+        grouped.push(digit);
+    }
+
+    let number = format!("{}{}{}{}", sign, grouped, decimal_separator, fraction);
+    let symbol = currency_symbol(currency);
+    match locale {
+        Locale::Uk | Locale::Us => format!("{}{}", symbol, number),
+        _ => format!("{} {}", number, symbol),
+    }
+}
+
+/// Decimals are stored exactly, but clients expect a plain json number.
+pub fn serialize_as_number<S: Serializer>(
+    amount: &BigDecimal,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(amount.to_f64().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+
+    use crate::money::{format_price, Locale};
+
+    #[test]
+    fn formats_prices_per_locale() {
+        let amount = BigDecimal::from_str("1299.9").unwrap();
+        assert_eq!(format_price(&amount, "EUR", Locale::De), "1.299,90 €");
+        assert_eq!(
+            format_price(&amount, "EUR", Locale::Fr),
+            "1\u{202f}299,90 €"
+        );
+        assert_eq!(format_price(&amount, "GBP", Locale::Uk), "£1,299.90");
+        assert_eq!(format_price(&amount, "USD", Locale::Us), "$1,299.90");
+
+        let small = BigDecimal::from_str("19.99").unwrap();
+        assert_eq!(format_price(&small, "EUR", Locale::De), "19,99 €");
+    }
+
+    #[test]
+    fn resolves_locale_from_accept_language() {
+        assert_eq!(Locale::from_accept_language("fr-CH, fr;q=0.9"), Locale::Fr);
+        assert_eq!(Locale::from_accept_language("en-GB,en;q=0.8"), Locale::Uk);
+        assert_eq!(Locale::from_accept_language("en"), Locale::Us);
+        assert_eq!(Locale::from_accept_language(""), Locale::De);
+    }
+}
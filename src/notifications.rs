@@ -0,0 +1,453 @@
+use std::{env, future::Future, str::FromStr, sync::Mutex, time::Duration};
+
+use anyhow::{anyhow, Result};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Utc;
+use log::{error, info};
+use serde::Serialize;
+use sqlx::{prelude::FromRow, PgConnection, Pool, Postgres};
+
+pub const KIND_PRICE_DROP: &str = "price_drop";
+
+const DEFAULT_PRICE_DROP_THRESHOLD_PERCENT: u32 = 10;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_DELIVERED: &str = "delivered";
+const STATUS_FAILED: &str = "failed";
+
+#[derive(Debug, Clone, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub id: i32,
+    pub item_id: i32,
+    pub kind: String,
+    #[serde(serialize_with = "crate::money::serialize_as_number")]
+    pub old_price_number: BigDecimal,
+    #[serde(serialize_with = "crate::money::serialize_as_number")]
+    pub new_price_number: BigDecimal,
+    #[serde(skip)]
+    pub attempts: i32,
+}
+
+/// Delivers a notification to the outside world (push gateway, tests, ...).
+pub trait NotificationSender {
+    fn send(&self, notification: &Notification) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Posts notifications as json to our push gateway, which resolves who has to be notified.
+pub struct WebhookSender {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSender {
+    pub fn new(url: String) -> WebhookSender {
+        WebhookSender {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+impl NotificationSender for WebhookSender {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let res = self
+            .client
+            .post(&self.url)
+            .timeout(Duration::from_secs(10))
+            .json(notification)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(anyhow!("push gateway responded with: {}", res.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the notifications in memory instead of sending them, for tests and local runs.
+/// Can be configured to fail, to exercise the retries.
+#[derive(Default)]
+pub struct StubSender {
+    pub sent: Mutex<Vec<Notification>>,
+    pub fail: bool,
+}
+
+impl NotificationSender for StubSender {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        if self.fail {
+            return Err(anyhow!("stub sender configured to fail"));
+        }
+        self.sent
+            .lock()
+            .expect("stub sender lock poisoned")
+            .push(notification.clone());
+        Ok(())
+    }
+}
+
+pub struct DeliveryConfig {
+    pub max_attempts: i32,
+    // the wait before the n-th retry is n * retry_backoff
+    pub retry_backoff: Duration,
+    // notifications claimed per delivery run
+    pub batch_size: i64,
+    // claimed notifications aren't picked up by another server before this passed,
+    // longer than sending a batch takes, shorter than waiting for a crashed server's ones
+    pub claim_timeout: Duration,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        DeliveryConfig {
+            max_attempts: 5,
+            retry_backoff: Duration::from_secs(60),
+            batch_size: 100,
+            claim_timeout: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DeliverySummary {
+    pub delivered: u32,
+    pub retrying: u32,
+    pub failed: u32,
+}
+
+/// Whether the price went down by more than `threshold_percent` (e.g. 10. for 10%).
+pub fn is_price_drop(
+    old_price: &BigDecimal,
+    new_price: &BigDecimal,
+    threshold_percent: &BigDecimal,
+) -> bool {
+    if *old_price <= BigDecimal::zero() || new_price >= old_price {
+        return false;
+    }
+    let drop_percent = (old_price - new_price) * BigDecimal::from(100) / old_price;
+    drop_percent >= *threshold_percent
+}
+
+/// The drop in percent past which a price drop is notified, from PRICE_DROP_THRESHOLD_PERCENT (10 if not set).
+pub fn price_drop_threshold() -> BigDecimal {
+    env::var("PRICE_DROP_THRESHOLD_PERCENT")
+        .ok()
+        .and_then(|percent| BigDecimal::from_str(&percent).ok())
+        .unwrap_or(BigDecimal::from(DEFAULT_PRICE_DROP_THRESHOLD_PERCENT))
+}
+
+/// The price a scrape saved for an item.
+#[derive(Debug)]
+pub struct ItemPrice {
+    pub item_id: i32,
+    pub price_number: BigDecimal,
+    pub price_currency: String,
+}
+
+/// A wishlisted item's last recorded price and the lower one a scrape saved.
+#[derive(Debug)]
+pub struct PriceChange {
+    pub item_id: i32,
+    pub old_price: BigDecimal,
+    pub new_price: BigDecimal,
+}
+
+/// Adds the saved prices that differ from the items' last recorded ones to the price history,
+/// in the transaction that saves them. Returns the ones of wishlisted items that went down.
+/// Prices in another currency than the last recorded one aren't compared.
+pub async fn record_prices(
+    conn: &mut PgConnection,
+    prices: &[ItemPrice],
+) -> Result<Vec<PriceChange>> {
+    let item_ids: Vec<i32> = prices.iter().map(|price| price.item_id).collect();
+    let numbers: Vec<BigDecimal> = prices.iter().map(|p| p.price_number.clone()).collect();
+    let currencies: Vec<&str> = prices.iter().map(|p| p.price_currency.as_str()).collect();
+    let drops: Vec<(i32, BigDecimal, BigDecimal)> = sqlx::query_as(
+        r#"
+WITH saved AS (
+    SELECT * FROM UNNEST($1::INTEGER[], $2::NUMERIC[], $3::TEXT[]) AS t(item_id, price_number, price_currency)
+), last AS (
+    SELECT DISTINCT ON (h.item_id) h.item_id, h.price_number, h.price_currency
+    FROM item_price_history h
+    WHERE h.item_id = ANY($1)
+    ORDER BY h.item_id, h.recorded_timestamp DESC, h.id DESC
+), recorded AS (
+    INSERT INTO item_price_history (item_id, price_number, price_currency, recorded_timestamp)
+    SELECT saved.item_id, saved.price_number, saved.price_currency, $4
+    FROM saved
+    LEFT JOIN last ON last.item_id = saved.item_id
+    WHERE last.item_id IS NULL OR last.price_number <> saved.price_number OR last.price_currency <> saved.price_currency
+)
+SELECT saved.item_id, last.price_number, saved.price_number
+FROM saved
+JOIN last ON last.item_id = saved.item_id AND last.price_currency = saved.price_currency
+WHERE saved.price_number < last.price_number
+    AND EXISTS (SELECT 1 FROM wishlist_item w WHERE w.item_id = saved.item_id);
+"#,
+    )
+    .bind(&item_ids)
+    .bind(&numbers)
+    .bind(&currencies)
+    .bind(Utc::now().timestamp_micros())
+    .fetch_all(conn)
+    .await?;
+
+    Ok(drops
+        .into_iter()
+        .map(|(item_id, old_price, new_price)| PriceChange {
+            item_id,
+            old_price,
+            new_price,
+        })
+        .collect())
+}
+
+/// Adds a price drop notification to the outbox for each change beyond the threshold,
+/// in the transaction that saves the new prices. Returns the count of enqueued notifications.
+pub async fn enqueue_price_drops(
+    conn: &mut PgConnection,
+    changes: &[PriceChange],
+    threshold_percent: &BigDecimal,
+) -> Result<u32> {
+    let drops: Vec<&PriceChange> = changes
+        .iter()
+        .filter(|change| is_price_drop(&change.old_price, &change.new_price, threshold_percent))
+        .collect();
+    if drops.is_empty() {
+        return Ok(0);
+    }
+
+    let item_ids: Vec<i32> = drops.iter().map(|drop| drop.item_id).collect();
+    let old_prices: Vec<BigDecimal> = drops.iter().map(|drop| drop.old_price.clone()).collect();
+    let new_prices: Vec<BigDecimal> = drops.iter().map(|drop| drop.new_price.clone()).collect();
+    let now = Utc::now().timestamp_micros();
+    sqlx::query(
+        r#"
+INSERT INTO notification_outbox (item_id, kind, old_price_number, new_price_number, status, created_timestamp, next_attempt_timestamp)
+SELECT item_id, $4, old_price_number, new_price_number, $5, $6, $6
+FROM UNNEST($1::INTEGER[], $2::NUMERIC[], $3::NUMERIC[]) AS t(item_id, old_price_number, new_price_number);
+"#,
+    )
+    .bind(&item_ids)
+    .bind(&old_prices)
+    .bind(&new_prices)
+    .bind(KIND_PRICE_DROP)
+    .bind(STATUS_PENDING)
+    .bind(now)
+    .execute(conn)
+    .await?;
+
+    Ok(drops.len() as u32)
+}
+
+/// Sends the pending notifications that are due, updating their delivery status.
+/// They're claimed first, so servers delivering the outbox at the same time don't send one twice.
+pub async fn deliver_pending<S: NotificationSender>(
+    pool: &Pool<Postgres>,
+    sender: &S,
+    config: &DeliveryConfig,
+) -> Result<DeliverySummary> {
+    let now = Utc::now().timestamp_micros();
+
+    // the claim postpones the next attempt, a server crashing before it's sent leaves it to be retried after the timeout
+    let mut pending: Vec<Notification> = sqlx::query_as(
+        r#"
+UPDATE notification_outbox
+SET next_attempt_timestamp = $3
+WHERE id IN (
+    SELECT id FROM notification_outbox
+    WHERE status = $1 AND next_attempt_timestamp <= $2
+    ORDER BY id
+    LIMIT $4
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING id, item_id, kind, old_price_number, new_price_number, attempts;
+"#,
+    )
+    .bind(STATUS_PENDING)
+    .bind(now)
+    .bind(now + config.claim_timeout.as_micros() as i64)
+    .bind(config.batch_size)
+    .fetch_all(pool)
+    .await?;
+    pending.sort_by_key(|notification| notification.id);
+
+    let mut summary = DeliverySummary::default();
+
+    for notification in pending {
+        match sender.send(&notification).await {
+            Ok(()) => {
+                sqlx::query(
+                    r#"
+UPDATE notification_outbox
+SET status = $2, attempts = attempts + 1, delivered_timestamp = $3, last_error = NULL
+WHERE id = $1;
+"#,
+                )
+                .bind(notification.id)
+                .bind(STATUS_DELIVERED)
+                .bind(Utc::now().timestamp_micros())
+                .execute(pool)
+                .await?;
+                summary.delivered += 1;
+            }
+            Err(e) => {
+                let attempts = notification.attempts + 1;
+                let status = if attempts >= config.max_attempts {
+                    summary.failed += 1;
+                    STATUS_FAILED
+                } else {
+                    summary.retrying += 1;
+                    STATUS_PENDING
+                };
+                let backoff_micros = config.retry_backoff.as_micros() as i64 * attempts as i64;
+
+                sqlx::query(
+                    r#"
+UPDATE notification_outbox
+SET status = $2, attempts = $3, last_error = $4, next_attempt_timestamp = $5
+WHERE id = $1;
+"#,
+                )
+                .bind(notification.id)
+                .bind(status)
+                .bind(attempts)
+                .bind(e.to_string())
+                .bind(Utc::now().timestamp_micros() + backoff_micros)
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Periodically delivers the outbox. Meant to be spawned next to the server.
+pub async fn run_delivery_loop<S: NotificationSender>(
+    pool: Pool<Postgres>,
+    sender: S,
+    config: DeliveryConfig,
+    interval: Duration,
+) {
+    loop {
+        match deliver_pending(&pool, &sender, &config).await {
+            Ok(summary) => {
+                if summary != DeliverySummary::default() {
+                    info!("notification delivery: {:?}", summary);
+                }
+            }
+            Err(e) => error!("error delivering notifications: {}", e),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{str::FromStr, time::Duration};
+
+    use anyhow::Result;
+    use bigdecimal::BigDecimal;
+
+    use crate::{
+        init_pool,
+        notifications::{
+            deliver_pending, enqueue_price_drops, is_price_drop, DeliveryConfig, PriceChange,
+            StubSender,
+        },
+    };
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn detects_price_drop_beyond_threshold() {
+        let threshold = dec("10");
+        assert!(is_price_drop(&dec("100"), &dec("80"), &threshold));
+        assert!(is_price_drop(&dec("100"), &dec("90"), &threshold));
+        assert!(!is_price_drop(&dec("100"), &dec("90.01"), &threshold));
+        assert!(!is_price_drop(&dec("100"), &dec("120"), &threshold));
+        assert!(!is_price_drop(&dec("0"), &dec("0"), &threshold));
+    }
+
+    #[tokio::test]
+    async fn delivers_and_retries_price_drops() -> Result<()> {
+        let pool = init_pool("5433").await;
+
+        let item: (i32,) = sqlx::query_as(
+            "INSERT INTO item (name_, type_, added_timestamp) VALUES ('mock', 'mock', 0) RETURNING id;",
+        )
+        .fetch_one(&pool)
+        .await?;
+
+        let change = |new_price: &str| PriceChange {
+            item_id: item.0,
+            old_price: dec("100"),
+            new_price: dec(new_price),
+        };
+        let mut conn = pool.acquire().await?;
+        let enqueued =
+            enqueue_price_drops(&mut conn, &[change("99"), change("50")], &dec("10")).await?;
+        assert_eq!(enqueued, 1);
+
+        let config = DeliveryConfig {
+            max_attempts: 2,
+            retry_backoff: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let failing = StubSender {
+            fail: true,
+            ..Default::default()
+        };
+        let summary = deliver_pending(&pool, &failing, &config).await?;
+        assert!(summary.retrying >= 1);
+
+        let sender = StubSender::default();
+        deliver_pending(&pool, &sender, &config).await?;
+        let sent = sender.sent.lock().unwrap();
+        assert!(sent
+            .iter()
+            .any(|n| n.item_id == item.0 && n.new_price_number == dec("50")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_deliveries_send_once() -> Result<()> {
+        let pool = init_pool("5433").await;
+        let item: (i32,) = sqlx::query_as(
+            "INSERT INTO item (name_, type_, added_timestamp) VALUES ('mock', 'mock', 0) RETURNING id;",
+        )
+        .fetch_one(&pool)
+        .await?;
+        let mut conn = pool.acquire().await?;
+        let change = PriceChange {
+            item_id: item.0,
+            old_price: dec("100"),
+            new_price: dec("50"),
+        };
+        enqueue_price_drops(&mut conn, &[change], &dec("10")).await?;
+
+        // e.g. two servers, each running the delivery loop
+        let config = DeliveryConfig::default();
+        let (first, second) = (StubSender::default(), StubSender::default());
+        let (a, b) = tokio::join!(
+            deliver_pending(&pool, &first, &config),
+            deliver_pending(&pool, &second, &config)
+        );
+        a?;
+        b?;
+
+        let sent_for_item = |sender: &StubSender| {
+            let sent = sender.sent.lock().unwrap();
+            sent.iter().filter(|n| n.item_id == item.0).count()
+        };
+        assert_eq!(sent_for_item(&first) + sent_for_item(&second), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    marketplace::Marketplaces,
+    scrapper::{parse_search_page, SearchResults},
+    selectors::SelectorProfiles,
+};
+
+const INDEX_FILE: &str = "index.jsonl";
+
+/// The pages fetched during a run, saved so extraction can be re-run without scraping again.
+/// Each page is a gzipped html file in `<dir>/<run>/`, listed in the run's index.jsonl.
+pub struct PageArchive {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedPage {
+    pub type_: String,
+    pub url: String,
+    pub timestamp: i64,
+    // relative to the run's directory
+    pub file: String,
+}
+
+/// The products of a target re-extracted from its archived pages.
+#[derive(Debug)]
+pub struct ReparsedTarget {
+    pub type_: String,
+    pub marketplace: String,
+    pub results: SearchResults,
+}
+
+impl PageArchive {
+    /// The archive of a run, `run` is the scrape run id or any other name for runs that aren't recorded.
+    pub fn open(archive_dir: &Path, run: &str) -> PageArchive {
+        PageArchive {
+            dir: archive_dir.join(run),
+        }
+    }
+
+    pub fn save(&self, type_: &str, url: &str, html: &str) -> Result<ArchivedPage> {
+        fs::create_dir_all(&self.dir)?;
+
+        let timestamp = Utc::now().timestamp_micros();
+        let page = ArchivedPage {
+            type_: type_.to_string(),
+            url: url.to_string(),
+            timestamp,
+            file: format!("{}.html.gz", timestamp),
+        };
+
+        let mut encoder = GzEncoder::new(
+            File::create(self.dir.join(&page.file))?,
+            Compression::default(),
+        );
+        encoder.write_all(html.as_bytes())?;
+        encoder.finish()?;
+
+        // the index is written after the page, so it only lists complete pages
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INDEX_FILE))?;
+        writeln!(index, "{}", serde_json::to_string(&page)?)?;
+
+        Ok(page)
+    }
+
+    /// The archived pages in the order they were fetched.
+    pub fn pages(&self) -> Result<Vec<ArchivedPage>> {
+        let index_path = self.dir.join(INDEX_FILE);
+        let index = File::open(&index_path)
+            .map_err(|e| anyhow!("error reading {}: {}", index_path.display(), e))?;
+
+        BufReader::new(index)
+            .lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+
+    pub fn read(&self, page: &ArchivedPage) -> Result<String> {
+        let mut html = String::new();
+        GzDecoder::new(File::open(self.dir.join(&page.file))?).read_to_string(&mut html)?;
+        Ok(html)
+    }
+
+    /// Runs the extraction again over all archived pages, grouped by target type and marketplace.
+    pub fn reparse(
+        &self,
+        marketplaces: &Marketplaces,
+        selectors: &SelectorProfiles,
+    ) -> Result<Vec<ReparsedTarget>> {
+        let mut targets: Vec<ReparsedTarget> = vec![];
+        for page in self.pages()? {
+            let marketplace = marketplaces.for_url(&page.url)?;
+            let profile = selectors.get(&marketplace.selector_profile)?;
+            let search_page = parse_search_page(&self.read(&page)?, profile, marketplace)?;
+            match targets
+                .iter_mut()
+                .find(|t| t.type_ == page.type_ && t.marketplace == marketplace.domain)
+            {
+                Some(target) => target.results.add_page(search_page),
+                None => {
+                    let mut results = SearchResults::default();
+                    results.add_page(search_page);
+                    targets.push(ReparsedTarget {
+                        type_: page.type_,
+                        marketplace: marketplace.domain.clone(),
+                        results,
+                    });
+                }
+            }
+        }
+        Ok(targets)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::Path};
+
+    use crate::{
+        marketplace::Marketplaces, page_archive::PageArchive, selectors::SelectorProfiles,
+    };
+
+    #[test]
+    fn saves_and_reparses_pages() {
+        let archive_dir = env::temp_dir().join(format!("page_archive_test_{}", std::process::id()));
+        let archive = PageArchive::open(&archive_dir, "1");
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/search");
+        let page1 = fs::read_to_string(fixtures.join("amazon_de_ringe_page1.html")).unwrap();
+        let last_page =
+            fs::read_to_string(fixtures.join("amazon_de_ringe_last_page.html")).unwrap();
+        archive
+            .save("ring", "https://www.amazon.de/s?k=ringe", &page1)
+            .unwrap();
+        archive
+            .save("ring", "https://www.amazon.de/s?k=ringe&page=2", &last_page)
+            .unwrap();
+
+        let pages = archive.pages().unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].url, "https://www.amazon.de/s?k=ringe&page=2");
+        assert_eq!(archive.read(&pages[0]).unwrap(), page1);
+
+        let selectors = SelectorProfiles::parse(include_str!("../selectors.toml")).unwrap();
+        let marketplaces = Marketplaces::parse(include_str!("../marketplaces.toml")).unwrap();
+        let targets = archive.reparse(&marketplaces, &selectors).unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].type_, "ring");
+        assert_eq!(targets[0].marketplace, "amazon.de");
+        assert_eq!(targets[0].results.pages_visited, 2);
+        assert_eq!(targets[0].results.products.len(), 6);
+
+        fs::remove_dir_all(&archive_dir).unwrap();
+    }
+}
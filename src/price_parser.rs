@@ -0,0 +1,132 @@
+use std::{fmt, str::FromStr};
+
+use bigdecimal::BigDecimal;
+
+use crate::money::Locale;
+
+/// Why a scraped price couldn't be parsed, so it's dropped instead of saved wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PriceParseError {
+    UnknownCurrency(String),
+    InvalidAmount(String),
+}
+
+impl fmt::Display for PriceParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriceParseError::UnknownCurrency(symbol) => write!(f, "unknown currency: {}", symbol),
+            PriceParseError::InvalidAmount(text) => write!(f, "invalid amount: {}", text),
+        }
+    }
+}
+
+impl std::error::Error for PriceParseError {}
+
+/// The ISO 4217 code of a currency symbol as shown by the marketplaces, e.g. "€" -> "EUR".
+pub fn currency_code(symbol: &str) -> Result<&'static str, PriceParseError> {
+    match symbol.trim() {
+        "€" | "EUR" => Ok("EUR"),
+        "£" | "GBP" => Ok("GBP"),
+        "$" | "US$" | "USD" => Ok("USD"),
+        other => Err(PriceParseError::UnknownCurrency(other.to_string())),
+    }
+}
+
+// (thousands separators, decimal separator), spaces include the non-breaking ones
+fn separators(locale: Locale) -> (&'static [char], char) {
+    match locale {
+        Locale::De | Locale::It | Locale::Es => (&['.', ' ', '\u{a0}', '\u{202f}'], ','),
+        Locale::Fr => (&[' ', '\u{a0}', '\u{202f}', '.'], ','),
+        Locale::Uk | Locale::Us => (&[',', ' ', '\u{a0}', '\u{202f}'], '.'),
+    }
+}
+
+/// Parses an amount written in a locale, e.g. "1.299,99" for German or "1,299.99" for US.
+/// Thousands separators have to separate groups of 3 digits, so a price in another locale isn't misread.
+pub fn parse_amount(text: &str, locale: Locale) -> Result<BigDecimal, PriceParseError> {
+    let invalid = || PriceParseError::InvalidAmount(text.to_string());
+    let (thousands_separators, decimal_separator) = separators(locale);
+
+    let trimmed = text.trim();
+    let (whole, fraction) = trimmed
+        .split_once(decimal_separator)
+        .unwrap_or((trimmed, ""));
+
+    let groups: Vec<&str> = whole.split(thousands_separators).collect();
+    let valid_groups = groups.iter().enumerate().all(|(i, group)| {
+        let digits = group.chars().all(|c| c.is_ascii_digit());
+        let length = match (i, groups.len()) {
+            (_, 1) => !group.is_empty(),
+            (0, _) => (1..=3).contains(&group.len()),
+            _ => group.len() == 3,
+        };
+        digits && length
+    });
+    if !valid_groups || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let number = if fraction.is_empty() {
+        groups.concat()
+    } else {
+        format!("{}.{}", groups.concat(), fraction)
+    };
+    BigDecimal::from_str(&number).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+
+    use crate::{
+        money::Locale,
+        price_parser::{currency_code, parse_amount, PriceParseError},
+    };
+
+    #[test]
+    fn parses_amounts_per_locale() {
+        let cases = [
+            ("14,99", Locale::De, "14.99"),
+            ("1.299,", Locale::De, "1299"),
+            ("1.299,90", Locale::De, "1299.90"),
+            ("1.299", Locale::It, "1299"),
+            ("12.345,5", Locale::Es, "12345.5"),
+            ("1\u{202f}299,90", Locale::Fr, "1299.90"),
+            ("1 299,90", Locale::Fr, "1299.90"),
+            ("1,299.90", Locale::Uk, "1299.90"),
+            ("1,299.", Locale::Us, "1299"),
+            ("0.99", Locale::Us, "0.99"),
+        ];
+        for (text, locale, expected) in cases {
+            assert_eq!(
+                parse_amount(text, locale),
+                Ok(BigDecimal::from_str(expected).unwrap()),
+                "{} in {:?}",
+                text,
+                locale
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_amounts_of_other_locales() {
+        // a US price read as German, the dot isn't followed by 3 digits
+        assert!(parse_amount("12.99", Locale::De).is_err());
+        assert!(parse_amount("", Locale::De).is_err());
+        assert!(parse_amount("12,5a", Locale::De).is_err());
+        assert!(parse_amount("1,2,3", Locale::Us).is_err());
+    }
+
+    #[test]
+    fn maps_currency_symbols() {
+        assert_eq!(currency_code("€"), Ok("EUR"));
+        assert_eq!(currency_code(" £ "), Ok("GBP"));
+        assert_eq!(currency_code("$"), Ok("USD"));
+        assert_eq!(
+            currency_code("zł"),
+            Err(PriceParseError::UnknownCurrency("zł".to_string()))
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Keyword rules that keep products of other kinds (phone cases, figurines, jewelry boxes)
+/// out of the catalog, read from a toml file (see relevance.toml).
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelevanceRules {
+    /// Products with a lower confidence aren't saved.
+    #[serde(default)]
+    pub reject_below: f64,
+    /// Products with a lower confidence are saved as drafts to be reviewed, the others are published.
+    #[serde(default)]
+    pub publish_from: f64,
+    /// Blocked in every category.
+    #[serde(default)]
+    pub block: Vec<String>,
+    #[serde(rename = "category", default)]
+    pub categories: HashMap<String, CategoryRules>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryRules {
+    /// Keywords of products of the category, e.g. "halskette" for necklaces.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub block: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Publish,
+    Review,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Classification {
+    // 0 to 1, 0.5 if no keyword matched
+    pub confidence: f64,
+    pub verdict: Verdict,
+    // the keywords found in the name
+    pub allowed: Vec<String>,
+    pub blocked: Vec<String>,
+}
+
+impl RelevanceRules {
+    pub fn from_file(path: &Path) -> Result<RelevanceRules> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("error reading {}: {}", path.display(), e))?;
+        RelevanceRules::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<RelevanceRules> {
+        let rules: RelevanceRules = toml::from_str(content)?;
+        if !(0.0..=rules.publish_from).contains(&rules.reject_below) || rules.publish_from > 1.0 {
+            return Err(anyhow!(
+                "thresholds have to be 0 <= reject_below <= publish_from <= 1"
+            ));
+        }
+        Ok(rules)
+    }
+
+    /// How likely a product of the category it's saved as belongs to it, judged by its name.
+    /// None for categories without rules, their products are published as they are.
+    pub fn classify(&self, category: &str, name: &str) -> Option<Classification> {
+        let rules = self.categories.get(category)?;
+        let name = name.to_lowercase();
+        let allowed = found_keywords(&name, rules.allow.iter());
+        let blocked = found_keywords(&name, rules.block.iter().chain(&self.block));
+
+        // a blocked keyword weighs twice an allowed one, so a phone case "with necklace" stays out
+        let confidence = (allowed.len() as f64 + 1.0)
+            / (allowed.len() as f64 + 2.0 * blocked.len() as f64 + 2.0);
+        let verdict = if confidence < self.reject_below {
+            Verdict::Reject
+        } else if confidence < self.publish_from {
+            Verdict::Review
+        } else {
+            Verdict::Publish
+        };
+
+        Some(Classification {
+            confidence,
+            verdict,
+            allowed,
+            blocked,
+        })
+    }
+}
+
+// the keywords the lowercase name contains
+fn found_keywords<'a>(name: &str, keywords: impl Iterator<Item = &'a String>) -> Vec<String> {
+    keywords
+        .filter(|keyword| name.contains(&keyword.to_lowercase()))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::relevance::{RelevanceRules, Verdict};
+
+    #[test]
+    fn classifies_by_keywords() {
+        let rules = RelevanceRules::parse(include_str!("../relevance.toml")).unwrap();
+        let verdict = |category: &str, name: &str| rules.classify(category, name).unwrap().verdict;
+
+        assert_eq!(
+            verdict("necklace", "Damen Halskette Silber mit Herz Anhänger"),
+            Verdict::Publish
+        );
+        assert_eq!(
+            verdict("necklace", "Naruto Figur Uzumaki Actionfigur 15cm"),
+            Verdict::Reject
+        );
+        assert_eq!(
+            verdict("necklace", "Handyhülle mit Kette für iPhone 15"),
+            Verdict::Review
+        );
+        // nothing to go by
+        let unknown = rules.classify("necklace", "Geschenk für Frauen").unwrap();
+        assert_eq!(unknown.verdict, Verdict::Review);
+        assert_eq!(unknown.confidence, 0.5);
+
+        assert!(rules.classify("no-rules", "Handyhülle").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_thresholds() {
+        assert!(RelevanceRules::parse("reject_below = 0.7\npublish_from = 0.5").is_err());
+        assert!(RelevanceRules::parse("reject_below = 0.2\npublish_from = 1.5").is_err());
+    }
+}
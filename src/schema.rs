@@ -0,0 +1,48 @@
+use anyhow::Result;
+use sqlx::{migrate::Migrator, Pool, Postgres};
+
+// migrations are embedded in the binary, see migrations/
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub async fn migrate(pool: &Pool<Postgres>) -> Result<()> {
+    MIGRATOR.run(pool).await?;
+    Ok(())
+}
+
+/// The schema version this binary expects, i.e. the version of the last embedded migration.
+pub fn expected_version() -> i64 {
+    MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The version of the last migration successfully applied to the database, 0 if none.
+pub async fn current_version(pool: &Pool<Postgres>) -> Result<i64> {
+    let row: (Option<i64>,) =
+        sqlx::query_as("SELECT MAX(version) FROM _sqlx_migrations WHERE success;")
+            .fetch_one(pool)
+            .await?;
+    Ok(row.0.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use crate::{
+        init_pool,
+        schema::{current_version, expected_version, migrate},
+    };
+
+    #[tokio::test]
+    async fn migrations_bring_schema_to_expected_version() -> Result<()> {
+        let pool = init_pool("5433").await;
+
+        migrate(&pool).await?;
+
+        assert_eq!(current_version(&pool).await?, expected_version());
+        Ok(())
+    }
+}
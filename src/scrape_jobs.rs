@@ -0,0 +1,291 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    marketplace::DEFAULT_MARKETPLACE,
+    scrapper::{ExtractionField, ScrapeTarget},
+};
+
+/// The searches to scrape, read from a toml file (see scrape_jobs.toml).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScrapeJobs {
+    /// Minimum share of result cards per field that has to be extracted, e.g. price = 0.8.
+    /// A target below it is failed without saving anything, since a selector most likely broke.
+    #[serde(default)]
+    pub min_success_ratio: HashMap<ExtractionField, f64>,
+    #[serde(rename = "target", default)]
+    pub targets: Vec<JobTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobTarget {
+    /// The type the results are saved as, e.g. "necklace".
+    pub category: String,
+    /// Search url, used as is. Either this or `keyword` has to be set.
+    pub url: Option<String>,
+    /// Search keyword, the url is built with `marketplace` and `sort`.
+    pub keyword: Option<String>,
+    /// Domain of the marketplace, see marketplaces.toml. Also applies to `url`.
+    #[serde(default = "default_marketplace")]
+    pub marketplace: String,
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u32,
+    #[serde(default)]
+    pub sort: SortOrder,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Incremental mode: stops after this many results in a row that are already saved.
+    /// The search is sorted by newest then, a `url` has to be sorted by newest already.
+    pub stop_after_known: Option<u32>,
+    #[serde(default)]
+    pub sponsored: SponsoredResults,
+}
+
+/// What to do with the ads among the search results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SponsoredResults {
+    /// Leave them out, so the feed isn't skewed toward ads.
+    #[default]
+    Skip,
+    /// Save them, flagged as sponsored.
+    Keep,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Relevance,
+    Newest,
+    PriceAsc,
+    PriceDesc,
+    Reviews,
+}
+
+impl SortOrder {
+    // the value of amazon's `s` search parameter
+    fn amazon_param(&self) -> &str {
+        match self {
+            SortOrder::Relevance => "relevanceblender",
+            SortOrder::Newest => "date-desc-rank",
+            SortOrder::PriceAsc => "price-asc-rank",
+            SortOrder::PriceDesc => "price-desc-rank",
+            SortOrder::Reviews => "review-rank",
+        }
+    }
+}
+
+fn default_marketplace() -> String {
+    DEFAULT_MARKETPLACE.to_string()
+}
+
+fn default_max_pages() -> u32 {
+    4
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl ScrapeJobs {
+    pub fn from_file(path: &Path) -> Result<ScrapeJobs> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("error reading {}: {}", path.display(), e))?;
+        ScrapeJobs::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<ScrapeJobs> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// The enabled targets with their search urls.
+    pub fn enabled_targets(&self) -> Result<Vec<ScrapeTarget>> {
+        self.targets
+            .iter()
+            .filter(|target| target.enabled)
+            .map(|target| target.to_scrape_target(&self.min_success_ratio))
+            .collect()
+    }
+
+    /// Whether the ads among the results of a category and marketplace are kept, e.g. when reparsing a run.
+    /// Only if all its targets keep them, enabled or not, as the run doesn't tell which one it was.
+    pub fn keeps_sponsored(&self, category: &str, marketplace: &str) -> bool {
+        let mut targets = self
+            .targets
+            .iter()
+            .filter(|target| target.category == category && target.marketplace == marketplace)
+            .peekable();
+        targets.peek().is_some() && targets.all(|target| target.sponsored == SponsoredResults::Keep)
+    }
+}
+
+impl JobTarget {
+    fn to_scrape_target(
+        &self,
+        min_success_ratio: &HashMap<ExtractionField, f64>,
+    ) -> Result<ScrapeTarget> {
+        let url = match (&self.url, &self.keyword) {
+            (Some(url), None) => url.clone(),
+            (None, Some(keyword)) => self.search_url(keyword)?,
+            _ => {
+                return Err(anyhow!(
+                    "target {} needs either a url or a keyword",
+                    self.category
+                ))
+            }
+        };
+
+        Ok(ScrapeTarget {
+            type_: self.category.clone(),
+            marketplace: self.marketplace.clone(),
+            url,
+            max_pages: self.max_pages,
+            min_success_ratio: min_success_ratio.clone(),
+            stop_after_known: self.stop_after_known,
+            keep_sponsored: self.sponsored == SponsoredResults::Keep,
+        })
+    }
+
+    // incremental targets only find the new products first if sorted by newest
+    fn sort(&self) -> Result<SortOrder> {
+        match (self.stop_after_known, self.sort) {
+            (None, sort) => Ok(sort),
+            (Some(_), SortOrder::Relevance | SortOrder::Newest) => Ok(SortOrder::Newest),
+            (Some(_), sort) => Err(anyhow!(
+                "target {} stops at known products, it can't be sorted by {:?}",
+                self.category,
+                sort
+            )),
+        }
+    }
+
+    fn search_url(&self, keyword: &str) -> Result<String> {
+        let sort = self.sort()?;
+        let mut url = Url::parse(&format!("https://www.{}/s", self.marketplace))?;
+        url.query_pairs_mut()
+            .append_pair("k", keyword)
+            .append_pair("i", "fashion");
+        if sort != SortOrder::Relevance {
+            url.query_pairs_mut().append_pair("s", sort.amazon_param());
+        }
+        Ok(url.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{marketplace::Marketplaces, scrape_jobs::ScrapeJobs, scrapper::ExtractionField};
+
+    #[test]
+    fn builds_targets_from_keywords_and_urls() {
+        let jobs = ScrapeJobs::parse(
+            r#"
+[min_success_ratio]
+price = 0.8
+
+[[target]]
+category = "anklet"
+keyword = "Fußkettchen"
+sort = "newest"
+max_pages = 2
+
+[[target]]
+category = "ring"
+url = "https://www.amazon.de/s?k=ringe&i=fashion"
+sponsored = "keep"
+
+[[target]]
+category = "charm"
+keyword = "Charms"
+enabled = false
+"#,
+        )
+        .unwrap();
+
+        let targets = jobs.enabled_targets().unwrap();
+        assert_eq!(targets.len(), 2);
+
+        assert_eq!(targets[0].type_, "anklet");
+        assert_eq!(
+            targets[0].url,
+            "https://www.amazon.de/s?k=Fu%C3%9Fkettchen&i=fashion&s=date-desc-rank"
+        );
+        assert_eq!(targets[0].max_pages, 2);
+        assert_eq!(
+            targets[0].min_success_ratio.get(&ExtractionField::Price),
+            Some(&0.8)
+        );
+
+        assert!(!targets[0].keep_sponsored);
+
+        assert_eq!(targets[1].url, "https://www.amazon.de/s?k=ringe&i=fashion");
+        assert_eq!(targets[1].max_pages, 4);
+        assert!(targets[1].keep_sponsored);
+
+        assert!(jobs.keeps_sponsored("ring", "amazon.de"));
+        assert!(!jobs.keeps_sponsored("ring", "amazon.fr"));
+        assert!(!jobs.keeps_sponsored("charm", "amazon.de"));
+    }
+
+    #[test]
+    fn sorts_incremental_targets_by_newest() {
+        let jobs = ScrapeJobs::parse(
+            r#"
+[[target]]
+category = "ring"
+keyword = "ringe"
+stop_after_known = 10
+"#,
+        )
+        .unwrap();
+        let targets = jobs.enabled_targets().unwrap();
+        assert_eq!(
+            targets[0].url,
+            "https://www.amazon.de/s?k=ringe&i=fashion&s=date-desc-rank"
+        );
+        assert_eq!(targets[0].stop_after_known, Some(10));
+
+        let jobs = ScrapeJobs::parse(
+            r#"
+[[target]]
+category = "ring"
+keyword = "ringe"
+sort = "price_asc"
+stop_after_known = 10
+"#,
+        )
+        .unwrap();
+        assert!(jobs.enabled_targets().is_err());
+    }
+
+    #[test]
+    fn rejects_target_without_search() {
+        let jobs = ScrapeJobs::parse(
+            r#"
+[[target]]
+category = "ring"
+"#,
+        )
+        .unwrap();
+
+        assert!(jobs.enabled_targets().is_err());
+    }
+
+    #[test]
+    fn repo_job_file_is_valid() {
+        let jobs = ScrapeJobs::parse(include_str!("../scrape_jobs.toml")).unwrap();
+        let targets = jobs.enabled_targets().unwrap();
+        assert!(!targets.is_empty());
+
+        let marketplaces = Marketplaces::parse(include_str!("../marketplaces.toml")).unwrap();
+        for target in targets {
+            assert!(marketplaces.get(&target.marketplace).is_ok());
+        }
+    }
+}
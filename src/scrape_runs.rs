@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{prelude::FromRow, types::Json, Pool, Postgres};
+
+use crate::{
+    canary::SuccessRatios,
+    scrapper::{ExtractionField, TargetReport},
+    selectors::SelectorMatches,
+};
+
+const STATUS_RUNNING: &str = "running";
+const STATUS_SUCCEEDED: &str = "succeeded";
+const STATUS_FAILED: &str = "failed";
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrapeRun {
+    pub id: i32,
+    pub status: String,
+    pub started_timestamp: i64,
+    pub finished_timestamp: Option<i64>,
+    #[sqlx(skip)]
+    pub targets: Vec<ScrapeRunTarget>,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrapeRunTarget {
+    #[serde(skip)]
+    pub run_id: i32,
+    pub type_: String,
+    pub url: String,
+    pub started_timestamp: i64,
+    pub finished_timestamp: i64,
+    pub pages_visited: i32,
+    pub items_found: i32,
+    pub items_inserted: i32,
+    pub items_updated: i32,
+    pub items_skipped: i32,
+    pub items_failed: i32,
+    pub result_cards: i32,
+    pub extraction_failures: Json<HashMap<ExtractionField, u32>>,
+    pub success_ratios: Json<SuccessRatios>,
+    pub page_success_ratios: Json<Vec<SuccessRatios>>,
+    // none for runs recorded before selector profiles
+    pub selector_version: Option<i32>,
+    pub selector_matches: Json<SelectorMatches>,
+    pub error: Option<String>,
+}
+
+/// Records the start of a run, returns its id.
+pub async fn start_run(pool: &Pool<Postgres>) -> Result<i32> {
+    let row: (i32,) = sqlx::query_as(
+        "INSERT INTO scrape_run (status, started_timestamp) VALUES ($1, $2) RETURNING id;",
+    )
+    .bind(STATUS_RUNNING)
+    .bind(Utc::now().timestamp_micros())
+    .fetch_one(pool)
+    .await?;
+    Ok(row.0)
+}
+
+pub async fn record_target(
+    pool: &Pool<Postgres>,
+    run_id: i32,
+    report: &TargetReport,
+) -> Result<()> {
+    let saved = report.saved.as_ref();
+    sqlx::query(
+        r#"
+INSERT INTO scrape_run_target (
+    run_id, type_, url, started_timestamp, finished_timestamp, pages_visited,
+    items_found, items_inserted, items_updated, items_skipped, items_failed,
+    result_cards, extraction_failures, success_ratios, page_success_ratios,
+    selector_version, selector_matches, error
+)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18);
+"#,
+    )
+    .bind(run_id)
+    .bind(&report.type_)
+    .bind(&report.url)
+    .bind(report.started_timestamp)
+    .bind(report.finished_timestamp)
+    .bind(report.pages_visited as i32)
+    .bind(report.products.len() as i32)
+    .bind(saved.map_or(0, |s| s.inserted as i32))
+    .bind(saved.map_or(0, |s| s.updated as i32))
+    .bind(saved.map_or(0, |s| s.skipped as i32))
+    .bind(saved.map_or(0, |s| s.failed as i32))
+    .bind(report.result_cards as i32)
+    .bind(Json(&report.extraction_failures))
+    .bind(Json(&report.success_ratios))
+    .bind(Json(&report.page_success_ratios))
+    .bind(report.selector_version as i32)
+    .bind(Json(&report.selector_matches))
+    .bind(&report.error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn finish_run(pool: &Pool<Postgres>, run_id: i32, succeeded: bool) -> Result<()> {
+    sqlx::query("UPDATE scrape_run SET status = $2, finished_timestamp = $3 WHERE id = $1;")
+        .bind(run_id)
+        .bind(if succeeded {
+            STATUS_SUCCEEDED
+        } else {
+            STATUS_FAILED
+        })
+        .bind(Utc::now().timestamp_micros())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The most recent runs with their targets, newest first.
+pub async fn load_runs(pool: &Pool<Postgres>, limit: i64) -> Result<Vec<ScrapeRun>> {
+    let mut runs: Vec<ScrapeRun> = sqlx::query_as(
+        r#"
+SELECT id, status, started_timestamp, finished_timestamp
+FROM scrape_run
+ORDER BY id DESC
+LIMIT $1;
+"#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    let run_ids: Vec<i32> = runs.iter().map(|run| run.id).collect();
+    let targets: Vec<ScrapeRunTarget> = sqlx::query_as(
+        r#"
+SELECT
+    run_id, type_, url, started_timestamp, finished_timestamp, pages_visited,
+    items_found, items_inserted, items_updated, items_skipped, items_failed,
+    result_cards, extraction_failures, success_ratios, page_success_ratios,
+    selector_version, selector_matches, error
+FROM scrape_run_target
+WHERE run_id = ANY($1)
+ORDER BY id;
+"#,
+    )
+    .bind(&run_ids)
+    .fetch_all(pool)
+    .await?;
+
+    for target in targets {
+        if let Some(run) = runs.iter_mut().find(|run| run.id == target.run_id) {
+            run.targets.push(target);
+        }
+    }
+
+    Ok(runs)
+}
+
+/// A run in the prometheus text format, to alert on failed runs and selector drift.
+pub fn to_metrics(run: &ScrapeRun) -> String {
+    let mut metrics = String::new();
+    let mut gauge = |name: &str, help: &str, values: Vec<(String, f64)>| {
+        metrics += &format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name);
+        for (labels, value) in values {
+            metrics += &format!("{}{} {}\n", name, labels, value);
+        }
+    };
+
+    gauge(
+        "scrape_run_failed",
+        "Whether the latest scrape run failed.",
+        vec![(String::new(), (run.status == STATUS_FAILED) as u8 as f64)],
+    );
+    gauge(
+        "scrape_run_started_timestamp_seconds",
+        "When the latest scrape run started.",
+        vec![(String::new(), (run.started_timestamp / 1_000_000) as f64)],
+    );
+    gauge(
+        "scrape_target_failed",
+        "Whether a target of the latest scrape run failed.",
+        run.targets
+            .iter()
+            .map(|target| {
+                let failed = target.error.is_some() || target.items_failed > 0;
+                (
+                    format!("{{type=\"{}\"}}", target.type_),
+                    failed as u8 as f64,
+                )
+            })
+            .collect(),
+    );
+    gauge(
+        "scrape_target_result_cards",
+        "Result cards found by a target of the latest scrape run.",
+        run.targets
+            .iter()
+            .map(|target| {
+                (
+                    format!("{{type=\"{}\"}}", target.type_),
+                    target.result_cards as f64,
+                )
+            })
+            .collect(),
+    );
+    gauge(
+        "scrape_target_success_ratio",
+        "Share of result cards a field was extracted from, by target of the latest scrape run.",
+        run.targets
+            .iter()
+            .flat_map(|target| {
+                target.success_ratios.iter().map(|(field, ratio)| {
+                    (
+                        format!(
+                            "{{type=\"{}\",field=\"{}\"}}",
+                            target.type_,
+                            field_name(*field)
+                        ),
+                        *ratio,
+                    )
+                })
+            })
+            .collect(),
+    );
+
+    metrics
+}
+
+// the name it's serialized with, e.g. "price"
+fn field_name(field: ExtractionField) -> String {
+    serde_json::to_value(field)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use anyhow::Result;
+
+    use crate::{
+        init_pool,
+        scrape_runs::{finish_run, load_runs, record_target, start_run, to_metrics},
+        scrapper::{ExtractionField, TargetReport},
+        selectors::SelectorField,
+    };
+
+    #[tokio::test]
+    async fn records_and_loads_runs() -> Result<()> {
+        let pool = init_pool("5433").await;
+
+        let run_id = start_run(&pool).await?;
+        let report = TargetReport {
+            type_: "mock".to_string(),
+            url: "https://foo.bar/s?k=mock".to_string(),
+            started_timestamp: 1,
+            finished_timestamp: 2,
+            pages_visited: 3,
+            stopped_at_known: false,
+            products: vec![],
+            sponsored_skipped: 0,
+            result_cards: 10,
+            extraction_failures: HashMap::from([(ExtractionField::Price, 4)]),
+            success_ratios: BTreeMap::from([(ExtractionField::Price, 0.6)]),
+            page_success_ratios: vec![BTreeMap::from([(ExtractionField::Price, 0.6)])],
+            selector_version: 1,
+            selector_matches: BTreeMap::from([(
+                SelectorField::PriceWhole,
+                BTreeMap::from([(".a-price-whole".to_string(), 6)]),
+            )]),
+            saved: None,
+            error: Some("mock error".to_string()),
+        };
+        record_target(&pool, run_id, &report).await?;
+        finish_run(&pool, run_id, false).await?;
+
+        let runs = load_runs(&pool, 100).await?;
+        let run = runs.iter().find(|run| run.id == run_id).unwrap();
+        assert_eq!(run.status, "failed");
+        assert_eq!(run.targets.len(), 1);
+        assert_eq!(run.targets[0].pages_visited, 3);
+        assert_eq!(
+            run.targets[0]
+                .extraction_failures
+                .get(&ExtractionField::Price),
+            Some(&4)
+        );
+        assert_eq!(run.targets[0].result_cards, 10);
+        assert_eq!(run.targets[0].success_ratios[&ExtractionField::Price], 0.6);
+        assert_eq!(run.targets[0].selector_version, Some(1));
+        assert_eq!(
+            run.targets[0].selector_matches[&SelectorField::PriceWhole][".a-price-whole"],
+            6
+        );
+
+        let metrics = to_metrics(run);
+        assert!(metrics.contains("scrape_run_failed 1\n"));
+        assert!(metrics.contains("scrape_target_failed{type=\"mock\"} 1\n"));
+        assert!(
+            metrics.contains("scrape_target_success_ratio{type=\"mock\",field=\"price\"} 0.6\n")
+        );
+
+        Ok(())
+    }
+}
@@ -1,42 +1,101 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+};
+
 use anyhow::{anyhow, Result};
+use bigdecimal::{BigDecimal, ToPrimitive};
 use chrono::Utc;
 use csv::Writer;
+use log::{debug, info, warn};
+use scraper::Html;
+use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
 use thirtyfour::prelude::*;
 use url::Url;
 
-async fn extract_link(container: &WebElement) -> Result<String> {
-    let link_wrappers = container
-        .find_all(By::ClassName("s-title-instructions-style"))
-        .await?;
+use crate::{
+    canary::{self, success_ratios, SuccessRatios},
+    dom::HtmlNode,
+    images::largest_src,
+    items::{STATUS_DRAFT, STATUS_PUBLISHED},
+    marketplace::{Marketplace, Marketplaces},
+    money::Locale,
+    notifications::{self, ItemPrice},
+    page_archive::PageArchive,
+    price_parser::{currency_code, parse_amount},
+    relevance::{Classification, RelevanceRules, Verdict},
+    selectors::{
+        merge_matches, SelectorField, SelectorMatcher, SelectorMatches, SelectorProfile,
+        SelectorProfiles,
+    },
+};
+
+// the product link, and whether it goes through the sponsored results' click tracker
+fn extract_link(
+    container: &impl HtmlNode,
+    matcher: &mut SelectorMatcher,
+    marketplace: &Marketplace,
+) -> Result<(String, bool)> {
+    let link_wrappers = matcher.find_all(container, SelectorField::LinkWrapper)?;
     if link_wrappers.len() == 1 {
         let link_wrapper = &link_wrappers[0];
-        let link = link_wrapper.find_all(By::Tag("a")).await?;
+        let link = matcher.find_all(link_wrapper, SelectorField::Link)?;
         // let link = child.find_all(By::Css(".a-link-normal.s-link-style.a-text-normal")).await?;
         // println!("link elements: {:?}", link.len());
         if link.len() == 1 {
-            println!("found 1 link");
+            debug!("found 1 link");
             let link = &link[0];
-            let href = link.attr("href").await?.unwrap_or_default();
+            let href = link.attr("href").unwrap_or_default();
 
-            let processed_href = process_infos_link(href)?;
+            let url = resolve_link(&href, marketplace)?;
+            let sponsored = is_sponsored_link(&url);
+            let processed_href = process_infos_link(href, marketplace)?;
             // println!("link: {:?}", href);
-            return Ok(processed_href);
+            Ok((processed_href, sponsored))
         } else {
-            return Err(anyhow!("no links or too many found: {}", link.len()));
+            Err(anyhow!("no links or too many found: {}", link.len()))
         }
     } else {
-        return Err(anyhow!(
+        Err(anyhow!(
             "no link wrappers or too many found: {}",
             link_wrappers.len()
-        ));
+        ))
     }
 }
 
-fn process_infos_link(link: String) -> Result<String> {
-    let base = "https://amazon.de";
-    let full_link = format!("{}{}", base, link);
-    let url = Url::parse(&full_link)?;
+// hrefs are mostly relative to the marketplace, some are absolute
+fn resolve_link(link: &str, marketplace: &Marketplace) -> Result<Url> {
+    Ok(Url::parse(&marketplace.base_url())?.join(link)?)
+}
+
+// ads link to the click tracker, e.g. /sspa/click?ie=UTF8&spc=...&url=%2FGold-Ring%2Fdp%2FB0D1K2L3M4%2Fref%3Dsr_1_2_sspa,
+// or through the ad server, e.g. https://aax-eu.amazon.de/x/c/RHlS.../https://www.amazon.de/Ring/dp/B0F6G7H8J9/ref=sxbs_aspa_sqa
+fn is_sponsored_link(url: &Url) -> bool {
+    url.path().starts_with("/sspa/") || url.host_str().is_some_and(|host| host.starts_with("aax-"))
+}
+
+// the product link of an ad, in the click tracker's url parameter or at the end of the ad server's path
+fn sponsored_target(url: &Url) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == "url")
+        .map(|(_, target)| target.into_owned())
+        .or_else(|| {
+            let path = url.path();
+            path.find("/http")
+                .map(|start| path[start + 1..].to_string())
+        })
+}
+
+fn process_infos_link(link: String, marketplace: &Marketplace) -> Result<String> {
+    let base = marketplace.base_url();
+    let mut url = resolve_link(&link, marketplace)?;
+
+    if is_sponsored_link(&url) {
+        let target = sponsored_target(&url)
+            .ok_or_else(|| anyhow!("sponsored link without product link: {}", link))?;
+        url = Url::parse(&base)?.join(&target)?;
+    }
 
     let segments: Vec<&str> = url
         .path_segments()
@@ -53,173 +112,376 @@ fn process_infos_link(link: String) -> Result<String> {
         .join("/");
 
     // reconstruct minimal url
-    let mut new_url = Url::parse("https://www.amazon.de").expect("Failed to parse base URL");
+    let mut new_url = Url::parse(&base)?;
     new_url.set_path(&format!("/{}", dp_path));
     new_url.set_query(None); // remove all query parameters
 
-    new_url.query_pairs_mut().append_pair("tag", "glam0d9-21");
+    if let Some(affiliate_tag) = &marketplace.affiliate_tag {
+        new_url.query_pairs_mut().append_pair("tag", affiliate_tag);
+    }
 
     Ok(new_url.to_string())
 }
 
-async fn extract_name(container: &WebElement) -> Result<String> {
-    let name = container
-        .find_all(By::Css(".a-size-base-plus.a-spacing-none"))
-        .await?;
+fn extract_name(container: &impl HtmlNode, matcher: &mut SelectorMatcher) -> Result<String> {
+    let name = matcher.find_all(container, SelectorField::NameWrapper)?;
 
     if name.len() == 1 {
         let name = &name[0];
         // println!("{:?}", name);
 
-        let spans = name.find_all(By::Tag("span")).await?;
+        let spans = matcher.find_all(name, SelectorField::Name)?;
         // println!("spans len: {:?}", spans);
         if spans.len() == 1 {
             let span = &spans[0];
-            let span_text: String = span.text().await?;
-            return Ok(span_text);
+            let span_text: String = span.text();
             // println!("text: {:?}", span_text);
+            Ok(span_text)
         } else {
-            return Err(anyhow!(
+            Err(anyhow!(
                 "multiple or no spans for name span: {}",
                 spans.len()
-            ));
+            ))
         }
     } else {
-        return Err(anyhow!("multiple or no spans for name: {}", name.len()));
+        Err(anyhow!("multiple or no spans for name: {}", name.len()))
     }
 }
 
-async fn extract_img(container: &WebElement) -> Result<String> {
-    let img = container.find(By::ClassName("s-image")).await?;
-    // img_src is low res which we don't need currently
-    // let img_src = img.attr("src").await?.unwrap_or_default();
-    let img_src_set = img.attr("srcset").await?.unwrap_or_default();
-    println!("image src set: {:?}", img_src_set);
-
-    let highest_res_img = extract_highest_res_img(&img_src_set)?;
-
-    Ok(highest_res_img)
-}
-
-// amazon adds an attribute to images in overview srcset with entries separated by ,
-// respectively containing a src and a string denoting resolution (like "2x")
-// we choose the highest res string, which empirically it's about right (slightly lower probably) for mobile devices.
-fn extract_highest_res_img(src_set: &str) -> Result<String> {
-    let resolution_entries = src_set.split(",").map(|e| e.trim()).collect::<Vec<&str>>();
+fn extract_img(container: &impl HtmlNode, matcher: &mut SelectorMatcher) -> Result<String> {
+    let img = matcher.find(container, SelectorField::Img)?;
+    let img_src_set = img.attr("srcset").unwrap_or_default();
+    debug!("image src set: {:?}", img_src_set);
 
-    // note: we assume highest resolution to be the last
-    // if we wanted to be more robust we'd parse all the resolutions and get highest
-    // but it seems that highest is always returned last so using that for now
-    if let Some(last) = resolution_entries.last() {
-        let parts = last.split_whitespace().collect::<Vec<&str>>();
-        if parts.len() == 2 {
-            let src = parts[0].trim();
-            let res = parts[1].trim();
-
-            if res != "3x" {
-                // either we got a higher than 3x resolution (seems not to exist currently? or they're not sorted as expected)
-                println!("warn: last resolution isn't 3x, are we getting the highest res?")
-            }
-
-            Ok(src.to_string())
-        } else {
-            // a resolution looks like this: 1x, 1.5x and so on
-            Err(anyhow!(
-                "unexpected: srcset entries should be `<imagepath> <resolution>`"
-            ))
-        }
+    // the api scales the image down to the client's screen, so we keep the largest
+    if img_src_set.trim().is_empty() {
+        img.attr("src")
+            .filter(|src| !src.is_empty())
+            .ok_or_else(|| anyhow!("image without src and srcset"))
     } else {
-        Err(anyhow!("unexpected: srcset didn't contain any images"))
+        largest_src(&img_src_set)
     }
 }
 
-#[derive(Debug)]
-struct Price {
-    str: String,
-    number: f32,
-    currency: String,
+#[derive(Debug, Default, Serialize)]
+pub struct Price {
+    #[serde(serialize_with = "crate::money::serialize_as_number")]
+    pub amount: BigDecimal,
+    // ISO 4217 code
+    pub currency: String,
 }
 
-async fn extract_price(container: &WebElement) -> Result<Price> {
-    let whole_part = container.find(By::ClassName("a-price-whole")).await?;
-    let fraction_part = container.find(By::ClassName("a-price-fraction")).await?;
-    let symbol_part = container.find(By::ClassName("a-price-symbol")).await?;
-
-    let symbol_text = symbol_part.text().await?;
-    let symbol = symbol_text.trim();
-    if symbol != "€" {
-        // we assume all prices are always euros, but a double check just in case
-        // TODO we should return an error here
-        println!("unexpected currency symbol: {}", symbol_part);
+// prices in other currencies are conversions we don't want to mix into the marketplace's items
+fn marketplace_currency(symbol: &str, marketplace: &Marketplace) -> Result<&'static str> {
+    let currency = currency_code(symbol)?;
+    if currency != marketplace.currency {
+        return Err(anyhow!(
+            "price in {} on {}, expected {}",
+            currency,
+            marketplace.domain,
+            marketplace.currency
+        ));
     }
+    Ok(currency)
+}
 
-    let price_str = format!(
-        "{}.{}",
-        whole_part.text().await?.trim(),
-        fraction_part.text().await?.trim()
-    );
+// a price with its symbol in one text, e.g. "39,90 €" or "£12.99"
+fn parse_price_text(text: &str, marketplace: &Marketplace) -> Result<Price> {
+    let is_amount = |c: &char| c.is_ascii_digit() || *c == ',' || *c == '.';
+    let amount: String = text.chars().filter(is_amount).collect();
+    let symbol: String = text
+        .chars()
+        .filter(|c| !is_amount(c) && !c.is_whitespace())
+        .collect();
+
+    let currency = marketplace_currency(&symbol, marketplace)?;
+    Ok(Price {
+        amount: parse_amount(&amount, marketplace.locale)?,
+        currency: currency.to_string(),
+    })
+}
 
-    let price_float = price_str.parse()?;
+fn extract_price(
+    container: &impl HtmlNode,
+    matcher: &mut SelectorMatcher,
+    marketplace: &Marketplace,
+) -> Result<Price> {
+    let whole_part = matcher.find(container, SelectorField::PriceWhole)?;
+    let fraction_part = matcher.find(container, SelectorField::PriceFraction)?;
+    let symbol_part = matcher.find(container, SelectorField::PriceSymbol)?;
+
+    let currency = marketplace_currency(&symbol_part.text(), marketplace)?;
+
+    // the whole part contains the decimal separator in its own span, e.g. `1.299<span>,</span>`
+    let amount_text = format!(
+        "{}{}",
+        whole_part.text().trim(),
+        fraction_part.text().trim()
+    );
+    let amount = parse_amount(&amount_text, marketplace.locale)?;
 
     Ok(Price {
-        str: price_str,
-        number: price_float,
-        currency: symbol.to_string(),
+        amount,
+        currency: currency.to_string(),
     })
 }
 
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ProductInfo {
     name: String,
     details_link: String,
     price: Price,
     img: String,
+    // optional, not every card shows them
+    brand: Option<String>,
+    // stars out of 5
+    rating: Option<f64>,
+    review_count: Option<i32>,
+    prime: bool,
+    // an ad, see is_sponsored_link
+    sponsored: bool,
 }
 
-async fn extract_product_info(container: &WebElement) -> Result<ProductInfo> {
-    match extract_link(container).await {
-        Ok(link) => match extract_name(container).await {
-            Ok(name) => match extract_price(container).await {
-                Ok(price) => match extract_img(container).await {
-                    Ok(img) => {
-                        return Ok(ProductInfo {
-                            name,
-                            details_link: link,
-                            price,
-                            img,
-                        })
-                    }
-                    Err(e) => return Err(anyhow!("error extracting img: {}", e)),
-                },
-                Err(e) => return Err(anyhow!("error extracting price: {}", e)),
-            },
-            Err(e) => return Err(anyhow!("error extracting name: {}", e)),
-        },
-        Err(e) => return Err(anyhow!("error extracting link: {}", e)),
+impl ProductInfo {
+    pub fn details_link(&self) -> &str {
+        &self.details_link
+    }
+
+    pub fn is_sponsored(&self) -> bool {
+        self.sponsored
     }
 }
 
-async fn extract_infos(container: &WebElement) -> Result<Vec<ProductInfo>> {
-    let children = container.find_all(By::ClassName("s-result-item")).await?;
-    // println!("children: {:?}", children.len());
+// the text of the first element matched by a field's selectors, None if nothing matched
+fn find_text(
+    container: &impl HtmlNode,
+    matcher: &mut SelectorMatcher,
+    field: SelectorField,
+) -> Result<Option<String>> {
+    Ok(matcher
+        .find_all(container, field)?
+        .first()
+        .map(|element| element.text())
+        .filter(|text| !text.is_empty()))
+}
+
+// e.g. "4,5 von 5 Sternen" or "4.5 out of 5 stars"
+fn parse_rating(text: &str, locale: Locale) -> Result<f64> {
+    let stars = text.split_whitespace().next().unwrap_or_default();
+    let rating = parse_amount(stars, locale)?
+        .to_f64()
+        .ok_or_else(|| anyhow!("invalid rating: {}", text))?;
+    if (0.0..=5.0).contains(&rating) {
+        Ok(rating)
+    } else {
+        Err(anyhow!("rating out of range: {}", text))
+    }
+}
+
+// e.g. "1.234" or "(1,234)"
+fn parse_review_count(text: &str, locale: Locale) -> Result<i32> {
+    let count = text.trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace());
+    parse_amount(count, locale)?
+        .to_i32()
+        .ok_or_else(|| anyhow!("invalid review count: {}", text))
+}
+
+/// Brand, rating, review count and prime of a result card.
+/// They're optional, so a missing or unreadable one is logged instead of failing the product.
+fn extract_card_attributes(
+    container: &impl HtmlNode,
+    matcher: &mut SelectorMatcher,
+    marketplace: &Marketplace,
+    info: &mut ProductInfo,
+) -> Result<()> {
+    info.brand = find_text(container, matcher, SelectorField::Brand)?;
+    info.rating = find_text(container, matcher, SelectorField::Rating)?.and_then(|text| {
+        parse_rating(&text, marketplace.locale)
+            .map_err(|e| debug!("skipping rating: {}", e))
+            .ok()
+    });
+    info.review_count =
+        find_text(container, matcher, SelectorField::ReviewCount)?.and_then(|text| {
+            parse_review_count(&text, marketplace.locale)
+                .map_err(|e| debug!("skipping review count: {}", e))
+                .ok()
+        });
+    info.prime = !matcher
+        .find_all(container, SelectorField::Prime)?
+        .is_empty();
+    Ok(())
+}
+
+/// The product field whose extraction failed, to track failures by reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionField {
+    Link,
+    Name,
+    Price,
+    Img,
+}
+
+struct ExtractionError {
+    field: ExtractionField,
+    error: anyhow::Error,
+}
+
+fn failed(field: ExtractionField) -> impl FnOnce(anyhow::Error) -> ExtractionError {
+    move |error| ExtractionError { field, error }
+}
 
-    let mut infos = vec![];
+// all fields are extracted even if one fails, so the success of each field can be measured
+fn extract_product_info(
+    container: &impl HtmlNode,
+    matcher: &mut SelectorMatcher,
+    marketplace: &Marketplace,
+) -> std::result::Result<ProductInfo, Vec<ExtractionError>> {
+    let link = extract_link(container, matcher, marketplace).map_err(failed(ExtractionField::Link));
+    let name = extract_name(container, matcher).map_err(failed(ExtractionField::Name));
+    let price =
+        extract_price(container, matcher, marketplace).map_err(failed(ExtractionField::Price));
+    let img = extract_img(container, matcher).map_err(failed(ExtractionField::Img));
+
+    match (link, name, price, img) {
+        (Ok((link, sponsored_link)), Ok(name), Ok(price), Ok(img)) => {
+            let sponsored_label = matcher
+                .find_all(container, SelectorField::Sponsored)
+                .is_ok_and(|labels| !labels.is_empty());
+            let mut info = ProductInfo {
+                name,
+                details_link: link,
+                price,
+                img,
+                sponsored: sponsored_link || sponsored_label,
+                ..Default::default()
+            };
+            if let Err(e) = extract_card_attributes(container, matcher, marketplace, &mut info) {
+                debug!(
+                    "error extracting attributes of {}: {}",
+                    info.details_link, e
+                );
+            }
+            Ok(info)
+        }
+        (link, name, price, img) => Err([link.err(), name.err(), price.err(), img.err()]
+            .into_iter()
+            .flatten()
+            .collect()),
+    }
+}
+
+/// Products extracted from search result pages, with what couldn't be extracted.
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    pub products: Vec<ProductInfo>,
+    pub pages_visited: u32,
+    pub result_cards: u32,
+    pub extraction_failures: HashMap<ExtractionField, u32>,
+    pub page_success_ratios: Vec<SuccessRatios>,
+    pub selector_matches: SelectorMatches,
+    // the crawl stopped before max_pages at products that are already saved
+    pub stopped_at_known: bool,
+}
+
+/// A search result page.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchPage {
+    pub products: Vec<ProductInfo>,
+    // the matched result items, including the ones nothing could be extracted from
+    pub result_cards: u32,
+    pub extraction_failures: HashMap<ExtractionField, u32>,
+    pub selector_matches: SelectorMatches,
+    pub is_last_page: bool,
+    // the absolute url of the "next" link, none on the last page
+    pub next_page_url: Option<String>,
+}
+
+/// Extracts the products of a search result page from its html.
+pub fn parse_search_page(
+    html: &str,
+    profile: &SelectorProfile,
+    marketplace: &Marketplace,
+) -> Result<SearchPage> {
+    let document = Html::parse_document(html);
+    let root = document.root_element();
+
+    let mut page = SearchPage::default();
+    let mut matcher = SelectorMatcher::new(profile);
+
+    let children = matcher.find_all(&root, SelectorField::ResultItem)?;
+    // println!("children: {:?}", children.len());
+    page.result_cards = children.len() as u32;
     for child in children {
-        match extract_product_info(&child).await {
-            Ok(info) => {
-                infos.push(info);
+        match extract_product_info(&child, &mut matcher, marketplace) {
+            Ok(info) => page.products.push(info),
+            Err(errors) => {
+                for e in errors {
+                    warn!("error extracting {:?}: {}", e.field, e.error);
+                    *page.extraction_failures.entry(e.field).or_default() += 1;
+                }
             }
-            Err(e) => println!("error extracting link: {}", e),
         }
     }
 
-    println!("finish a page! extracted infos: {:?}", infos.len());
+    let next_page_disabled = matcher.find_all(&root, SelectorField::NextPageDisabled)?;
+    if next_page_disabled.is_empty() {
+        let next_page = matcher.find_all(&root, SelectorField::NextPage)?;
+        page.next_page_url = match next_page.first().and_then(|link| link.attr("href")) {
+            Some(href) => Some(Url::parse(&marketplace.base_url())?.join(href)?.to_string()),
+            None => None,
+        };
+    }
+    page.is_last_page = page.next_page_url.is_none();
+    page.selector_matches = matcher.matches;
+
+    info!("finish a page! extracted infos: {:?}", page.products.len());
+
+    Ok(page)
+}
+
+impl SearchPage {
+    pub fn success_ratios(&self) -> SuccessRatios {
+        success_ratios(self.result_cards, &self.extraction_failures)
+    }
+}
+
+impl SearchResults {
+    pub fn add_page(&mut self, page: SearchPage) {
+        self.page_success_ratios.push(page.success_ratios());
+        self.result_cards += page.result_cards;
+        self.products.extend(page.products);
+        for (field, count) in page.extraction_failures {
+            *self.extraction_failures.entry(field).or_default() += count;
+        }
+        merge_matches(&mut self.selector_matches, page.selector_matches);
+        self.pages_visited += 1;
+    }
+
+    pub fn success_ratios(&self) -> SuccessRatios {
+        success_ratios(self.result_cards, &self.extraction_failures)
+    }
+}
 
-    Ok(infos)
+// the elements matching the first selector variant that matches any
+async fn find_all_in_driver(driver: &WebDriver, variants: &[String]) -> Result<Vec<WebElement>> {
+    for variant in variants {
+        let found = driver.find_all(By::Css(variant)).await?;
+        if !found.is_empty() {
+            return Ok(found);
+        }
+    }
+    Ok(vec![])
 }
 
-async fn reject_cookies_if_dialog_present(driver: &WebDriver) -> Result<()> {
+async fn reject_cookies_if_dialog_present(
+    driver: &WebDriver,
+    marketplace: &Marketplace,
+) -> Result<()> {
     // using find all as a way to allow optional, surely there's a better way?
-    let reject_cookies_buttons = driver.find_all(By::Id("sp-cc-rejectall-link")).await?;
+    let reject_cookies_buttons = find_all_in_driver(driver, &marketplace.cookie_consent).await?;
     if reject_cookies_buttons.len() == 1 {
         let reject_cookies_button = &reject_cookies_buttons[0];
         reject_cookies_button
@@ -230,9 +492,10 @@ async fn reject_cookies_if_dialog_present(driver: &WebDriver) -> Result<()> {
     Ok(())
 }
 
-async fn hover_all_details_thumbnails(driver: &WebDriver) -> Result<()> {
-    let thumbnails = driver.find_all(By::ClassName("imageThumbnail")).await?;
-    println!("found thumbnails: {}", thumbnails.len());
+async fn hover_all_details_thumbnails(driver: &WebDriver, profile: &SelectorProfile) -> Result<()> {
+    let thumbnails =
+        find_all_in_driver(driver, profile.variants(SelectorField::DetailsThumbnail)).await?;
+    debug!("found thumbnails: {}", thumbnails.len());
 
     for thumbnail in thumbnails {
         let action_chain = driver.action_chain();
@@ -245,132 +508,374 @@ async fn hover_all_details_thumbnails(driver: &WebDriver) -> Result<()> {
     Ok(())
 }
 
-async fn extract_imgs_from_details(driver: &WebDriver) -> Result<Vec<String>> {
-    // reject cookies - otherwise overlay on the way to hover for images
-    reject_cookies_if_dialog_present(driver).await?;
+/// What's taken from a product's details page.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductDetails {
+    pub name: String,
+    // the gallery in display order
+    pub images: Vec<String>,
+    // feature bullets, one per line, or the description if there are none
+    pub descr: String,
+    // from the product overview table, if listed
+    pub brand: Option<String>,
+    pub material: Option<String>,
+    pub colour: Option<String>,
+    // shared by the variants (sizes, colours) Amazon lists as separate products
+    pub parent_asin: Option<String>,
+    pub variants: Vec<ProductVariant>,
+}
 
-    // hover so all big images are added to dom
-    hover_all_details_thumbnails(driver).await?;
+/// A variant of a product, e.g. another size, with its own ASIN and price.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductVariant {
+    pub asin: String,
+    // dimension -> value, e.g. {"color": "Silber", "size": "54"}
+    pub dimensions: BTreeMap<String, String>,
+    pub price: Price,
+    // the swatch, only shown for some dimensions like colours
+    pub img: Option<String>,
+    pub details_link: String,
+}
 
-    let image_wrappers = driver.find_all(By::ClassName("imgTagWrapper")).await?;
-    // println!("found image wrappers: {}", image_wrappers.len());
+/// Extracts the details of a product page from its html.
+/// The gallery only contains all big images after hovering the thumbnails, see extract_product_details.
+pub fn parse_details_page(
+    html: &str,
+    profile: &SelectorProfile,
+    marketplace: &Marketplace,
+) -> Result<ProductDetails> {
+    let document = Html::parse_document(html);
+    extract_details(
+        &document.root_element(),
+        &mut SelectorMatcher::new(profile),
+        marketplace,
+    )
+}
 
-    let mut imgs = vec![];
-    for image_wrapper in image_wrappers {
-        let imgs_children = image_wrapper.find_all(By::Tag("img")).await?;
+// e.g. "size" for the variation with id "variation_size_name"
+fn variation_dimension(variation: &impl HtmlNode) -> Option<String> {
+    let id = variation.attr("id")?;
+    let dimension = id.strip_prefix("variation_")?;
+    Some(
+        dimension
+            .strip_suffix("_name")
+            .unwrap_or(dimension)
+            .to_string(),
+    )
+}
 
-        if imgs_children.len() == 1 {
-            let img = &imgs_children[0];
-            // Get the src attribute from the img element
-            let img_src = img.attr("src").await?.unwrap_or_default();
-            imgs.push(img_src);
+// the twister lists the values of each dimension, each swatch links to the variant with that value
+// and the selected values of the other dimensions
+fn extract_variants(
+    root: &impl HtmlNode,
+    matcher: &mut SelectorMatcher,
+    marketplace: &Marketplace,
+) -> Result<Vec<ProductVariant>> {
+    let mut variations = vec![];
+    let mut selected = BTreeMap::new();
+    for variation in matcher.find_all(root, SelectorField::DetailsVariation)? {
+        if let Some(dimension) = variation_dimension(&variation) {
+            if let Some(value) = find_text(
+                &variation,
+                matcher,
+                SelectorField::DetailsVariationSelection,
+            )? {
+                selected.insert(dimension.clone(), value);
+            }
+            variations.push((dimension, variation));
         }
     }
 
-    Ok(imgs)
+    let mut variants: Vec<ProductVariant> = vec![];
+    for (dimension, variation) in &variations {
+        for swatch in matcher.find_all(variation, SelectorField::DetailsVariant)? {
+            let asin = swatch.attr("data-defaultasin").unwrap_or_default();
+            if asin.is_empty() || variants.iter().any(|variant| variant.asin == asin) {
+                continue;
+            }
+            // unavailable variants have no price
+            let Some(price_text) = find_text(&swatch, matcher, SelectorField::DetailsVariantPrice)?
+            else {
+                continue;
+            };
+            let price = match parse_price_text(&price_text, marketplace) {
+                Ok(price) => price,
+                Err(e) => {
+                    debug!("skipping variant {}: {}", asin, e);
+                    continue;
+                }
+            };
+            // text swatches like sizes, or images like colours
+            let value = matcher
+                .find_all(&swatch, SelectorField::DetailsVariantValue)?
+                .first()
+                .map(|value| match value.text() {
+                    text if text.is_empty() => value.attr("alt").unwrap_or_default(),
+                    text => text,
+                })
+                .unwrap_or_default();
+            let img = swatch
+                .find_all("img")?
+                .first()
+                .and_then(|img| img.attr("src"))
+                .filter(|src| !src.is_empty());
+
+            let mut dimensions = selected.clone();
+            dimensions.insert(dimension.clone(), value);
+            variants.push(ProductVariant {
+                details_link: process_infos_link(format!("/dp/{}", asin), marketplace)?,
+                asin,
+                dimensions,
+                price,
+                img,
+            });
+        }
+    }
+    Ok(variants)
 }
 
-struct ProductDetailsInfos {
-    name: String,
-}
+fn extract_details(
+    root: &impl HtmlNode,
+    matcher: &mut SelectorMatcher,
+    marketplace: &Marketplace,
+) -> Result<ProductDetails> {
+    // without a title this isn't a product page, e.g. a captcha
+    let name = matcher.find(root, SelectorField::DetailsTitle)?.text();
+
+    let mut images: Vec<String> = vec![];
+    for image_wrapper in matcher.find_all(root, SelectorField::DetailsImageWrapper)? {
+        let imgs_children = image_wrapper.find_all("img")?;
+        if imgs_children.len() == 1 {
+            let img_src = imgs_children[0].attr("src").unwrap_or_default();
+            if !img_src.is_empty() && !images.contains(&img_src) {
+                images.push(img_src);
+            }
+        }
+    }
 
-struct ProductDetails {
-    name: String,
-    images: Vec<String>,
-}
+    let bullets: Vec<String> = matcher
+        .find_all(root, SelectorField::DetailsBullets)?
+        .iter()
+        .map(|bullet| bullet.text())
+        .filter(|bullet| !bullet.is_empty())
+        .collect();
+    let descr = if bullets.is_empty() {
+        matcher
+            .find_all(root, SelectorField::DetailsDescription)?
+            .first()
+            .map(|description| description.text())
+            .unwrap_or_default()
+    } else {
+        bullets.join("\n")
+    };
 
-async fn extract_infos_from_details(driver: &WebDriver) -> Result<ProductDetailsInfos> {
-    let name_span = driver
-        .find(By::Id("productTitle"))
-        .await
-        .expect("no title in details");
-    let name: String = name_span.text().await?;
-    Ok(ProductDetailsInfos { name })
+    Ok(ProductDetails {
+        name,
+        images,
+        descr,
+        brand: find_text(root, matcher, SelectorField::DetailsBrand)?,
+        material: find_text(root, matcher, SelectorField::DetailsMaterial)?,
+        colour: find_text(root, matcher, SelectorField::DetailsColour)?,
+        parent_asin: matcher
+            .find_all(root, SelectorField::DetailsParentAsin)?
+            .first()
+            .and_then(|input| input.attr("value"))
+            .filter(|asin| !asin.is_empty()),
+        variants: extract_variants(root, matcher, marketplace)?,
+    })
 }
 
-async fn extract_product_details(driver: &WebDriver, link: &str) -> Result<ProductDetails> {
+pub async fn extract_product_details(
+    driver: &WebDriver,
+    profile: &SelectorProfile,
+    marketplace: &Marketplace,
+    link: &str,
+) -> Result<ProductDetails> {
     driver.goto(link).await?;
 
-    let images = extract_imgs_from_details(driver).await?;
-    let infos = extract_infos_from_details(driver).await?;
+    // reject cookies - otherwise overlay on the way to hover for images
+    reject_cookies_if_dialog_present(driver, marketplace).await?;
 
-    Ok(ProductDetails {
-        name: infos.name.clone(),
-        images,
-    })
-}
+    // hover so all big images are added to dom
+    hover_all_details_thumbnails(driver, profile).await?;
 
-async fn is_in_last_page(driver: &WebDriver) -> Result<bool> {
-    let next_page_disabled = driver
-        .find_all(By::Css(
-            ".s-pagination-item.s-pagination-next.s-pagination-disabled",
-        ))
-        .await?;
-    Ok(!next_page_disabled.is_empty())
+    parse_details_page(&driver.source().await?, profile, marketplace)
 }
 
-pub async fn extract_infos_for_all_pages(
+// fetches the page source, archiving it if an archive is passed
+async fn fetch_page_source(
     driver: &WebDriver,
-    root_url: &str,
-    max_pages: u32,
-) -> Result<Vec<ProductInfo>> {
-    driver.goto(root_url).await?;
-
-    // reject cookies - otherwise overlay might get in the way
-    reject_cookies_if_dialog_present(driver).await?;
-
-    let mut next_page = 2;
-    let mut all_links = vec![];
+    type_: &str,
+    archive: Option<&PageArchive>,
+) -> Result<String> {
+    let html = driver.source().await?;
+    if let Some(archive) = archive {
+        let url = driver.current_url().await?;
+        // the scrape shouldn't fail because of the archive
+        if let Err(e) = archive.save(type_, url.as_str(), &html) {
+            warn!("error archiving page {}: {}", url, e);
+        }
+    }
+    Ok(html)
+}
 
-    while !is_in_last_page(&driver)
-        .await
-        .expect("error checking is last page")
-        && next_page < max_pages
-    {
-        let container = driver.find(By::ClassName("s-main-slot")).await?;
-        let page_links = extract_infos(&container).await.expect("...");
-        all_links.extend(page_links);
-
-        let next_page_par = format!("&page={}", next_page);
-        driver
-            .goto(format!("{}{}", root_url, next_page_par))
+/// The vendor links among the given ones that are already saved.
+pub async fn load_known_links(pool: &Pool<Postgres>, links: &[String]) -> Result<HashSet<String>> {
+    let rows: Vec<(String,)> =
+        sqlx::query_as("SELECT vendor_link FROM item WHERE vendor_link = ANY($1);")
+            .bind(links)
+            .fetch_all(pool)
             .await?;
+    Ok(rows.into_iter().map(|(link,)| link).collect())
+}
 
-        next_page += 1;
+/// Counts the consecutive known products of a page on from `streak`, the count of the previous pages.
+/// True once `stop_after` are counted, a new product starts the count again.
+/// Ads are left out, they're placed regardless of the sort.
+fn reaches_known_streak(
+    products: &[ProductInfo],
+    known: &HashSet<String>,
+    streak: &mut u32,
+    stop_after: u32,
+) -> bool {
+    for product in products.iter().filter(|product| !product.sponsored) {
+        if known.contains(&product.details_link) {
+            *streak += 1;
+            if *streak >= stop_after {
+                return true;
+            }
+        } else {
+            *streak = 0;
+        }
     }
+    false
+}
 
-    println!("finished extracting links for {} pages", next_page - 1);
-    Ok(all_links)
+/// The result pages of a search, from its first page on by following the "next" links.
+pub struct ResultPages<'a> {
+    driver: &'a WebDriver,
+    target: &'a ScrapeTarget,
+    marketplace: &'a Marketplace,
+    profile: &'a SelectorProfile,
+    archive: Option<&'a PageArchive>,
+    next_url: Option<String>,
+    visited_urls: HashSet<String>,
 }
 
-#[allow(unused)]
-async fn collect_details(driver: &WebDriver, infos: &[ProductInfo]) -> Result<Vec<ProductDetails>> {
-    let mut product_details: Vec<ProductDetails> = vec![];
-    for info in infos {
-        // example link to test just one page (comment loop)
-        // let link = "https://amazon.de/sspa/click?ie=UTF8&spc=MTo1NzU5Nzg0NjQ1NTU0NDQ3OjE3MzkyODE1MTc6c3BfYXRmOjMwMDM0NTQ5MTgzMDkzMjo6MDo6&url=%2Fs-Oliver-Damen-Ring-Edelstahl-Swarovski-Kristalle-Breite%2Fdp%2FB07FD729LJ%2Fref%3Dsr_1_1_sspa%3Fdib%3DeyJ2IjoiMSJ9.bMcM1L4llnp90s8_saI8idf565ai9cImntwXUe2M0C30kPlwkWo5Mq4k3_LOO0SUP9Sofu-TCe-QjGORDi_lOu27QdUkGVQWDkjZXEkky-eccusHY51_ZOZkG17ILR6j87jO3SruEkxLu8sLzm2M7EP6395CeKLq3xLgZsCr1FWu1PM-L2BtlBGGPGKgP6VPXRnH_EK8ZyqTJCR-L74_FOdgcQ7VB_brEhBqiDW4enmS4wKswD83qTT5kzf08WvEkMwIYAOBQkfef6kEkzc6v7W3IWaTZ5ScMQUc7i1zfjU.IPHI5Mxj-tn6zvcwFmWLZHZjVOKsEfuykyn9d1QDWCE%26dib_tag%3Dse%26keywords%3Dringe%26qid%3D1739281517%26s%3Dapparel%26sr%3D1-1-spons%26sp_csd%3Dd2lkZ2V0TmFtZT1zcF9hdGY%26psc%3D1".to_string();
+impl<'a> ResultPages<'a> {
+    pub fn new(
+        driver: &'a WebDriver,
+        target: &'a ScrapeTarget,
+        marketplace: &'a Marketplace,
+        profile: &'a SelectorProfile,
+        archive: Option<&'a PageArchive>,
+    ) -> ResultPages<'a> {
+        ResultPages {
+            driver,
+            target,
+            marketplace,
+            profile,
+            archive,
+            next_url: Some(target.url.clone()),
+            visited_urls: HashSet::new(),
+        }
+    }
 
-        match extract_product_details(driver, &info.details_link).await {
-            Ok(details) => {
-                product_details.push(details);
-            }
-            Err(e) => {
-                println!(
-                    "Couldn't extract product details for: {}, error: {}",
-                    info.details_link, e
-                )
+    pub fn pages_visited(&self) -> u32 {
+        self.visited_urls.len() as u32
+    }
+
+    /// Visits and extracts the next page, none after the last page or `max_pages`.
+    pub async fn next_page(&mut self) -> Result<Option<SearchPage>> {
+        if self.pages_visited() >= self.target.max_pages {
+            return Ok(None);
+        }
+        // a "next" link back to a visited page would loop forever
+        let Some(url) = self
+            .next_url
+            .take()
+            .filter(|url| !self.visited_urls.contains(url))
+        else {
+            return Ok(None);
+        };
+
+        self.driver.goto(&url).await?;
+        if self.visited_urls.is_empty() {
+            // reject cookies - otherwise overlay might get in the way
+            reject_cookies_if_dialog_present(self.driver, self.marketplace).await?;
+        }
+        self.visited_urls.insert(url);
+
+        let html = fetch_page_source(self.driver, &self.target.type_, self.archive).await?;
+        let page = parse_search_page(&html, self.profile, self.marketplace)?;
+        self.next_url = page.next_page_url.clone();
+        Ok(Some(page))
+    }
+}
+
+/// Collects the products of the search result pages of a target.
+/// With a pool and `stop_after_known` set, stops at the page where that many saved products follow each other,
+/// as the results are sorted by newest the rest are saved already.
+pub async fn extract_infos_for_all_pages(
+    driver: &WebDriver,
+    pool: Option<&Pool<Postgres>>,
+    target: &ScrapeTarget,
+    marketplace: &Marketplace,
+    profile: &SelectorProfile,
+    archive: Option<&PageArchive>,
+) -> Result<SearchResults> {
+    let mut pages = ResultPages::new(driver, target, marketplace, profile, archive);
+    let mut results = SearchResults::default();
+    let mut known_streak = 0;
+
+    while let Some(page) = pages.next_page().await? {
+        // the whole target is checked before saving, this is to see on which page it started
+        if let Err(e) = canary::check(&page.success_ratios(), &target.min_success_ratio) {
+            warn!("page {} of {}: {}", pages.pages_visited(), target.type_, e);
+        }
+        let stop_at_known = match (pool, target.stop_after_known) {
+            (Some(pool), Some(stop_after)) => {
+                let links: Vec<String> = page
+                    .products
+                    .iter()
+                    .map(|p| p.details_link.clone())
+                    .collect();
+                let known = load_known_links(pool, &links).await?;
+                reaches_known_streak(&page.products, &known, &mut known_streak, stop_after)
             }
+            _ => false,
+        };
+        results.add_page(page);
+        if stop_at_known {
+            info!(
+                "stopping {} at page {}: {} known products in a row",
+                target.type_,
+                pages.pages_visited(),
+                known_streak
+            );
+            results.stopped_at_known = true;
+            break;
         }
     }
-    Ok(product_details)
+
+    info!(
+        "finished extracting links for {} pages",
+        results.pages_visited
+    );
+    Ok(results)
 }
 
-pub fn to_csv(infos: &[ProductInfo]) -> Result<()> {
-    let mut wtr = Writer::from_path("product_infos.csv")?;
+pub fn to_csv<'a, W: Write>(
+    infos: impl IntoIterator<Item = &'a ProductInfo>,
+    writer: W,
+) -> Result<()> {
+    let mut wtr = Writer::from_writer(writer);
     for info in infos {
         wtr.write_record(&[
             info.name.clone(),
-            info.price.str.clone(),
+            info.price.amount.to_string(),
             info.price.currency.clone(),
             info.img.clone(),
             info.details_link.clone(),
@@ -381,81 +886,667 @@ pub fn to_csv(infos: &[ProductInfo]) -> Result<()> {
     Ok(())
 }
 
+/// A search whose results are saved as the given type, see scrape_jobs.
+#[derive(Debug, Clone)]
+pub struct ScrapeTarget {
+    pub type_: String,
+    // domain, see marketplaces.toml
+    pub marketplace: String,
+    pub url: String,
+    pub max_pages: u32,
+    // below these shares of result cards a field was extracted from, nothing is saved
+    pub min_success_ratio: HashMap<ExtractionField, f64>,
+    // stop after this many saved products in a row, none crawls up to max_pages
+    pub stop_after_known: Option<u32>,
+    // ads are left out of the saved products unless kept
+    pub keep_sponsored: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetReport {
+    pub type_: String,
+    pub url: String,
+    pub started_timestamp: i64,
+    pub finished_timestamp: i64,
+    pub pages_visited: u32,
+    // stopped before max_pages at products that are already saved
+    pub stopped_at_known: bool,
+    pub products: Vec<ProductInfo>,
+    // ads left out of the products
+    pub sponsored_skipped: u32,
+    pub result_cards: u32,
+    pub extraction_failures: HashMap<ExtractionField, u32>,
+    pub success_ratios: SuccessRatios,
+    pub page_success_ratios: Vec<SuccessRatios>,
+    // version of the selector profiles used
+    pub selector_version: u32,
+    pub selector_matches: SelectorMatches,
+    // none for dry runs or if scraping failed
+    pub saved: Option<SaveSummary>,
+    pub error: Option<String>,
+}
+
+impl TargetReport {
+    pub fn is_failure(&self) -> bool {
+        self.error.is_some() || self.saved.as_ref().is_some_and(|saved| saved.failed > 0)
+    }
+}
+
+// the marketplace of a target and the selector profile of its layout
+fn resolve_marketplace<'a>(
+    target: &ScrapeTarget,
+    marketplaces: &'a Marketplaces,
+    selectors: &'a SelectorProfiles,
+) -> Result<(&'a Marketplace, &'a SelectorProfile)> {
+    let marketplace = marketplaces.get(&target.marketplace)?;
+    let profile = selectors.get(&marketplace.selector_profile)?;
+    Ok((marketplace, profile))
+}
+
+/// Leaves the ads out of the products unless they're kept, see ScrapeTarget.
+/// Returns how many were left out.
+pub fn skip_sponsored(products: &mut Vec<ProductInfo>, keep_sponsored: bool) -> u32 {
+    if keep_sponsored {
+        return 0;
+    }
+    let found = products.len();
+    products.retain(|product| !product.sponsored);
+    (found - products.len()) as u32
+}
+
+/// Scrapes a target and saves the results, unless no pool is passed (dry run).
+/// The fetched pages are saved to the archive if one is passed.
+pub async fn scrape_target(
+    driver: &WebDriver,
+    pool: Option<&Pool<Postgres>>,
+    target: &ScrapeTarget,
+    marketplaces: &Marketplaces,
+    selectors: &SelectorProfiles,
+    relevance: &RelevanceRules,
+    archive: Option<&PageArchive>,
+) -> TargetReport {
+    let mut report = TargetReport {
+        type_: target.type_.clone(),
+        url: target.url.clone(),
+        started_timestamp: Utc::now().timestamp_micros(),
+        finished_timestamp: 0,
+        pages_visited: 0,
+        stopped_at_known: false,
+        products: vec![],
+        sponsored_skipped: 0,
+        result_cards: 0,
+        extraction_failures: HashMap::new(),
+        success_ratios: SuccessRatios::new(),
+        page_success_ratios: vec![],
+        selector_version: selectors.version,
+        selector_matches: SelectorMatches::new(),
+        saved: None,
+        error: None,
+    };
+
+    let results = match resolve_marketplace(target, marketplaces, selectors) {
+        Ok((marketplace, profile)) => {
+            extract_infos_for_all_pages(driver, pool, target, marketplace, profile, archive).await
+        }
+        Err(e) => Err(e),
+    };
+    match results {
+        Ok(results) => {
+            report.success_ratios = results.success_ratios();
+            // a broken selector would otherwise just save less products
+            if let Err(e) = canary::check(&report.success_ratios, &target.min_success_ratio) {
+                report.error = Some(e.to_string());
+            }
+            report.pages_visited = results.pages_visited;
+            report.stopped_at_known = results.stopped_at_known;
+            report.products = results.products;
+            report.sponsored_skipped = skip_sponsored(&mut report.products, target.keep_sponsored);
+            report.result_cards = results.result_cards;
+            report.extraction_failures = results.extraction_failures;
+            report.page_success_ratios = results.page_success_ratios;
+            report.selector_matches = results.selector_matches;
+        }
+        Err(e) => report.error = Some(format!("error extracting products: {}", e)),
+    }
+
+    if let (Some(pool), None) = (pool, &report.error) {
+        match save_products_to_db(
+            pool,
+            &report.products,
+            &target.type_,
+            &target.marketplace,
+            relevance,
+        )
+        .await
+        {
+            Ok(saved) => report.saved = Some(saved),
+            Err(e) => report.error = Some(format!("error saving products: {}", e)),
+        }
+    }
+
+    report.finished_timestamp = Utc::now().timestamp_micros();
+    report
+}
+
+// rows per insert statement and transaction
+const SAVE_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SaveSummary {
+    pub inserted: u32,
+    pub updated: u32,
+    // duplicates within the saved products
+    pub skipped: u32,
+    // products in batches whose transaction failed
+    pub failed: u32,
+    // not saved as they don't belong to the category, see relevance.rs
+    pub rejected: u32,
+    // inserted as drafts to be reviewed
+    pub in_review: u32,
+    // updated items whose price dropped past the threshold, see notifications.rs
+    pub price_drops: u32,
+}
+
+/// Upserts the products by vendor link, one transaction per batch.
+/// A failing batch is rolled back and counted as failed, the other batches are still saved.
+/// Products the relevance rules reject aren't saved, new ones to be reviewed are saved as drafts.
 pub async fn save_products_to_db(
     pool: &Pool<Postgres>,
     infos: &[ProductInfo],
     type_: &str,
-) -> Result<()> {
-    for info in infos {
-        save_product_to_db(pool, info, type_).await?;
+    marketplace: &str,
+    relevance: &RelevanceRules,
+) -> Result<SaveSummary> {
+    let mut summary = SaveSummary::default();
+
+    let mut seen_links = HashSet::new();
+    let unique_infos: Vec<&ProductInfo> = infos
+        .iter()
+        .filter(|info| seen_links.insert(info.details_link.as_str()))
+        .collect();
+    summary.skipped = (infos.len() - unique_infos.len()) as u32;
+
+    let mut relevant_infos = vec![];
+    for info in unique_infos {
+        let classification = relevance.classify(type_, &info.name);
+        match classification {
+            Some(classification) if classification.verdict == Verdict::Reject => {
+                debug!("rejecting {} as {}: {:?}", info.name, type_, classification);
+                summary.rejected += 1;
+            }
+            classification => relevant_infos.push((info, classification)),
+        }
     }
 
-    Ok(())
+    let price_drop_threshold = notifications::price_drop_threshold();
+    for batch in relevant_infos.chunks(SAVE_BATCH_SIZE) {
+        match save_batch_to_db(pool, batch, type_, marketplace, &price_drop_threshold).await {
+            Ok(saved) => {
+                summary.inserted += saved.inserted;
+                summary.updated += saved.updated;
+                summary.in_review += saved.in_review;
+                summary.price_drops += saved.price_drops;
+            }
+            Err(e) => {
+                warn!("error saving batch of {} products: {}", batch.len(), e);
+                summary.failed += batch.len() as u32;
+            }
+        }
+    }
+
+    info!("saved products: {:?}", summary);
+
+    Ok(summary)
 }
 
-async fn save_product_to_db(pool: &Pool<Postgres>, infos: &ProductInfo, type_: &str) -> Result<()> {
-    let row: (i32,) = sqlx::query_as(
+/// Counts the inserted, updated, inserted as drafts and dropped in price products.
+async fn save_batch_to_db(
+    pool: &Pool<Postgres>,
+    classified_infos: &[(&ProductInfo, Option<Classification>)],
+    type_: &str,
+    marketplace: &str,
+    price_drop_threshold: &BigDecimal,
+) -> Result<SaveSummary> {
+    let infos: Vec<&ProductInfo> = classified_infos.iter().map(|(info, _)| *info).collect();
+    let confidences: Vec<Option<f64>> = classified_infos
+        .iter()
+        .map(|(_, c)| c.as_ref().map(|c| c.confidence))
+        .collect();
+    let statuses: Vec<&str> = classified_infos
+        .iter()
+        .map(|(_, c)| match c {
+            Some(c) if c.verdict == Verdict::Review => STATUS_DRAFT,
+            _ => STATUS_PUBLISHED,
+        })
+        .collect();
+    let names: Vec<String> = infos.iter().map(|i| i.name.clone()).collect();
+    let prices: Vec<BigDecimal> = infos.iter().map(|i| i.price.amount.clone()).collect();
+    let currencies: Vec<String> = infos.iter().map(|i| i.price.currency.clone()).collect();
+    let links: Vec<String> = infos.iter().map(|i| i.details_link.clone()).collect();
+    let brands: Vec<Option<String>> = infos.iter().map(|i| i.brand.clone()).collect();
+    let ratings: Vec<Option<f64>> = infos.iter().map(|i| i.rating).collect();
+    let review_counts: Vec<Option<i32>> = infos.iter().map(|i| i.review_count).collect();
+    let primes: Vec<bool> = infos.iter().map(|i| i.prime).collect();
+    let sponsored: Vec<bool> = infos.iter().map(|i| i.sponsored).collect();
+    // one microsecond apart, so the timestamps used to page through the items stay unique and ordered
+    let now = Utc::now().timestamp_micros();
+    let timestamps: Vec<i64> = (0..infos.len() as i64).map(|i| now + i).collect();
+
+    let mut tx = pool.begin().await?;
+
+    // updates keep added_timestamp, so known products don't show up again as new
+    // and keep the brand, rating and review count if the card didn't show them this time
+    // an item stays sponsored only as long as it wasn't found as an organic result
+    // the status is only set for new items, so a review isn't undone by the next scrape
+    let rows: Vec<(i32, String, bool, String, BigDecimal, String)> = sqlx::query_as(
         r#"
-INSERT INTO item (name_, price, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8) 
-RETURNING id;
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr, marketplace, brand, rating, review_count, prime, is_sponsored, relevance_confidence, status)
+SELECT name_, price_number, price_currency, vendor_link, $5, added_timestamp, '', $7, brand, rating, review_count, prime, is_sponsored, relevance_confidence, status
+FROM UNNEST($1::VARCHAR[], $2::NUMERIC[], $3::VARCHAR[], $4::VARCHAR[], $6::BIGINT[], $8::TEXT[], $9::FLOAT8[], $10::INTEGER[], $11::BOOLEAN[], $12::BOOLEAN[], $13::FLOAT8[], $14::VARCHAR[])
+    AS t(name_, price_number, price_currency, vendor_link, added_timestamp, brand, rating, review_count, prime, is_sponsored, relevance_confidence, status)
+ON CONFLICT (vendor_link) DO UPDATE
+SET name_ = EXCLUDED.name_, price_number = EXCLUDED.price_number, price_currency = EXCLUDED.price_currency, type_ = EXCLUDED.type_,
+    brand = COALESCE(EXCLUDED.brand, item.brand), rating = COALESCE(EXCLUDED.rating, item.rating),
+    review_count = COALESCE(EXCLUDED.review_count, item.review_count), prime = EXCLUDED.prime,
+    is_sponsored = item.is_sponsored AND EXCLUDED.is_sponsored,
+    relevance_confidence = COALESCE(EXCLUDED.relevance_confidence, item.relevance_confidence)
+RETURNING id, vendor_link, (xmax = 0) AS inserted, status, price_number, price_currency;
 "#,
     )
-    .bind(infos.name.clone())
-    .bind(infos.price.str.clone())
-    .bind(infos.price.number)
-    .bind(infos.price.currency.clone())
-    .bind(infos.details_link.clone())
+    .bind(&names)
+    .bind(&prices)
+    .bind(&currencies)
+    .bind(&links)
     .bind(type_)
-    .bind(Utc::now().timestamp_micros())
-    .bind("")
-    .fetch_one(pool)
-    .await
-    .expect("Failed to insert product");
+    .bind(&timestamps)
+    .bind(marketplace)
+    .bind(&brands)
+    .bind(&ratings)
+    .bind(&review_counts)
+    .bind(&primes)
+    .bind(&sponsored)
+    .bind(&confidences)
+    .bind(&statuses)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // pictures are only added for new items, updated ones already have theirs
+    let imgs_by_link: HashMap<&str, &str> = infos
+        .iter()
+        .map(|i| (i.details_link.as_str(), i.img.as_str()))
+        .collect();
+    let mut pic_item_ids = vec![];
+    let mut pic_urls = vec![];
+    for (id, link, inserted, ..) in &rows {
+        if *inserted {
+            if let Some(img) = imgs_by_link.get(link.as_str()) {
+                pic_item_ids.push(*id);
+                pic_urls.push(img.to_string());
+            }
+        }
+    }
 
     sqlx::query(
         r#"
 INSERT INTO item_pic (item_id, url)
-VALUES ($1, $2);
+SELECT * FROM UNNEST($1::INTEGER[], $2::TEXT[]);
 "#,
     )
-    .bind(row.0)
-    .bind(infos.img.clone())
-    .execute(pool)
-    .await
-    .expect("Failed to insert product picture");
+    .bind(&pic_item_ids)
+    .bind(&pic_urls)
+    .execute(&mut *tx)
+    .await?;
 
-    Ok(())
+    let prices: Vec<ItemPrice> = rows
+        .iter()
+        .map(|(id, _, _, _, price_number, price_currency)| ItemPrice {
+            item_id: *id,
+            price_number: price_number.clone(),
+            price_currency: price_currency.clone(),
+        })
+        .collect();
+    // the changed prices go to the price history, the drops of wishlisted items are notified
+    let price_changes = notifications::record_prices(&mut tx, &prices).await?;
+    let price_drops =
+        notifications::enqueue_price_drops(&mut tx, &price_changes, price_drop_threshold).await?;
+
+    tx.commit().await?;
+
+    let inserted = rows.iter().filter(|(_, _, inserted, ..)| *inserted).count() as u32;
+    let in_review = rows
+        .iter()
+        .filter(|(_, _, inserted, status, ..)| *inserted && status == STATUS_DRAFT)
+        .count() as u32;
+    Ok(SaveSummary {
+        inserted,
+        updated: rows.len() as u32 - inserted,
+        in_review,
+        price_drops,
+        ..Default::default()
+    })
+}
+
+/// How extracted products compare to the saved items with the same vendor link.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductDiff {
+    // links of the products that aren't saved
+    pub new: Vec<String>,
+    pub changed: Vec<ProductChange>,
+    pub unchanged: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProductChange {
+    pub link: String,
+    pub saved_name: String,
+    pub name: String,
+    #[serde(serialize_with = "crate::money::serialize_as_number")]
+    pub saved_price: BigDecimal,
+    #[serde(serialize_with = "crate::money::serialize_as_number")]
+    pub price: BigDecimal,
+}
+
+pub async fn diff_products_with_db(
+    pool: &Pool<Postgres>,
+    infos: &[ProductInfo],
+) -> Result<ProductDiff> {
+    let links: Vec<&str> = infos.iter().map(|i| i.details_link.as_str()).collect();
+    let rows: Vec<(String, String, BigDecimal)> = sqlx::query_as(
+        "SELECT vendor_link, name_, price_number FROM item WHERE vendor_link = ANY($1);",
+    )
+    .bind(&links)
+    .fetch_all(pool)
+    .await?;
+    let saved: HashMap<String, (String, BigDecimal)> = rows
+        .into_iter()
+        .map(|(link, name, price)| (link, (name, price)))
+        .collect();
+
+    let mut diff = ProductDiff::default();
+    for info in infos {
+        match saved.get(&info.details_link) {
+            None => diff.new.push(info.details_link.clone()),
+            Some((name, price)) if *name != info.name || *price != info.price.amount => {
+                diff.changed.push(ProductChange {
+                    link: info.details_link.clone(),
+                    saved_name: name.clone(),
+                    name: info.name.clone(),
+                    saved_price: price.clone(),
+                    price: info.price.amount.clone(),
+                })
+            }
+            Some(_) => diff.unchanged += 1,
+        }
+    }
+
+    Ok(diff)
 }
 
 #[cfg(test)]
 mod test {
+    use std::str::FromStr;
+
+    use std::{collections::HashSet, env, fs, path::Path};
+
     use anyhow::Result;
-    use thirtyfour::{DesiredCapabilities, WebDriver};
+    use bigdecimal::BigDecimal;
 
     use crate::{
         init_pool,
+        items::{load_items, to_db_filters, Filters},
+        marketplace::Marketplaces,
+        relevance::RelevanceRules,
         scrapper::{
-            extract_infos_for_all_pages, save_product_to_db, save_products_to_db, Price,
+            diff_products_with_db, is_sponsored_link, parse_details_page, parse_search_page,
+            process_infos_link, reaches_known_streak, resolve_link, save_products_to_db, Price,
             ProductInfo,
         },
+        selectors::SelectorProfiles,
+        wishlists::add_to_wishlist,
     };
 
+    // a saved search result, the tests override the fields they look at
+    fn product(link: &str, price: &str) -> ProductInfo {
+        ProductInfo {
+            name: "mock product".to_string(),
+            details_link: link.to_string(),
+            price: Price {
+                amount: BigDecimal::from_str(price).unwrap(),
+                currency: "EUR".to_string(),
+            },
+            img: "https://doesntexist.com/foo.png".to_string(),
+            ..Default::default()
+        }
+    }
+
+    // compares the parsed fixture with its golden file, UPDATE_GOLDEN=1 rewrites the golden file
+    fn assert_matches_golden(fixture: &str) -> serde_json::Value {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/search");
+        let html = fs::read_to_string(dir.join(format!("{}.html", fixture))).unwrap();
+        let profiles = SelectorProfiles::parse(include_str!("../selectors.toml")).unwrap();
+        let marketplaces = Marketplaces::parse(include_str!("../marketplaces.toml")).unwrap();
+        let page = parse_search_page(
+            &html,
+            profiles.get("amazon").unwrap(),
+            marketplaces.get("amazon.de").unwrap(),
+        )
+        .unwrap();
+        let page = serde_json::to_value(page).unwrap();
+
+        let golden_path = dir.join(format!("{}.golden.json", fixture));
+        if env::var("UPDATE_GOLDEN").is_ok() {
+            fs::write(
+                &golden_path,
+                serde_json::to_string_pretty(&page).unwrap() + "\n",
+            )
+            .unwrap();
+        }
+        let golden: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&golden_path).unwrap()).unwrap();
+        assert_eq!(page, golden);
+
+        page
+    }
+
+    #[test]
+    fn parses_search_page() {
+        let page = assert_matches_golden("amazon_de_ringe_page1");
+        let products = page["products"].as_array().unwrap();
+        assert_eq!(products.len(), 5);
+        // the header widget isn't a result card
+        assert_eq!(page["resultCards"], 6);
+        let sponsored: Vec<bool> = products
+            .iter()
+            .map(|product| product["sponsored"].as_bool().unwrap())
+            .collect();
+        assert_eq!(sponsored, vec![false, true, true, true, false]);
+        assert_eq!(
+            products[1]["detailsLink"],
+            "https://www.amazon.de/dp/B0D1K2L3M4?tag=glam0d9-21"
+        );
+        assert_eq!(page["isLastPage"], false);
+        assert_eq!(
+            page["nextPageUrl"],
+            "https://www.amazon.de/s?k=ringe&i=fashion&page=2"
+        );
+    }
+
+    #[test]
+    fn parses_last_search_page() {
+        let page = assert_matches_golden("amazon_de_ringe_last_page");
+        assert_eq!(page["products"].as_array().unwrap().len(), 1);
+        assert_eq!(page["isLastPage"], true);
+        assert!(page["nextPageUrl"].is_null());
+    }
+
+    #[test]
+    fn builds_product_links_per_marketplace() {
+        let marketplaces = Marketplaces::parse(include_str!("../marketplaces.toml")).unwrap();
+        let href = "/Ring-Silber/dp/B07FD729LJ/ref=sr_1_1?keywords=ringe".to_string();
+
+        let de = process_infos_link(href.clone(), marketplaces.get("amazon.de").unwrap()).unwrap();
+        assert_eq!(de, "https://www.amazon.de/dp/B07FD729LJ?tag=glam0d9-21");
+
+        // no affiliate tag configured
+        let uk = process_infos_link(href, marketplaces.get("amazon.co.uk").unwrap()).unwrap();
+        assert_eq!(uk, "https://www.amazon.co.uk/dp/B07FD729LJ");
+
+        // an ad, through the click tracker
+        let uk_market = marketplaces.get("amazon.co.uk").unwrap();
+        let sponsored = "/sspa/click?ie=UTF8&spc=MTo0&url=%2FRing%2Fdp%2FB0D1K2L3M4%2Fref%3Dsr_1_2_sspa%3Fpsc%3D1&sp_csd=d2lk";
+        assert!(is_sponsored_link(
+            &resolve_link(sponsored, uk_market).unwrap()
+        ));
+        let link = process_infos_link(sponsored.to_string(), uk_market).unwrap();
+        assert_eq!(link, "https://www.amazon.co.uk/dp/B0D1K2L3M4");
+
+        // the click tracker linked absolutely
+        let absolute = format!("https://www.amazon.co.uk{}", sponsored);
+        assert!(is_sponsored_link(
+            &resolve_link(&absolute, uk_market).unwrap()
+        ));
+        let link = process_infos_link(absolute, uk_market).unwrap();
+        assert_eq!(link, "https://www.amazon.co.uk/dp/B0D1K2L3M4");
+
+        // an ad server redirect
+        let redirect = "https://aax-eu.amazon.co.uk/x/c/RHlS/https://www.amazon.co.uk/Ring/dp/B0F6G7H8J9/ref=sxbs_aspa_sqa?psc=1";
+        assert!(is_sponsored_link(
+            &resolve_link(redirect, uk_market).unwrap()
+        ));
+        let link = process_infos_link(redirect.to_string(), uk_market).unwrap();
+        assert_eq!(link, "https://www.amazon.co.uk/dp/B0F6G7H8J9");
+
+        // a path merely containing sspa isn't an ad
+        let organic = "https://www.amazon.co.uk/Ring-sspa/dp/B07FD729LJ";
+        assert!(!is_sponsored_link(
+            &resolve_link(organic, uk_market).unwrap()
+        ));
+    }
+
+    #[test]
+    fn stops_at_consecutive_known_products() {
+        let products: Vec<ProductInfo> = ["a", "known1", "b", "known2", "known3"]
+            .iter()
+            .map(|link| ProductInfo {
+                details_link: link.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let known: HashSet<String> = ["known1", "known2", "known3"]
+            .iter()
+            .map(|link| link.to_string())
+            .collect();
+
+        let mut streak = 0;
+        assert!(!reaches_known_streak(&products, &known, &mut streak, 3));
+        assert_eq!(streak, 2);
+        // the streak goes on over pages
+        assert!(reaches_known_streak(
+            &products[3..4],
+            &known,
+            &mut streak,
+            3
+        ));
+
+        let mut streak = 0;
+        assert!(reaches_known_streak(&products, &known, &mut streak, 2));
+    }
+
+    #[test]
+    fn parses_details_page() {
+        let html = fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("fixtures/details/amazon_de_ring_details.html"),
+        )
+        .unwrap();
+        let profiles = SelectorProfiles::parse(include_str!("../selectors.toml")).unwrap();
+        let marketplaces = Marketplaces::parse(include_str!("../marketplaces.toml")).unwrap();
+        let profile = profiles.get("amazon").unwrap();
+        let de = marketplaces.get("amazon.de").unwrap();
+        let details = parse_details_page(&html, profile, de).unwrap();
+
+        assert_eq!(
+            details.name,
+            "s.Oliver Damen Ring Edelstahl mit Swarovski Kristallen, Breite 4 mm"
+        );
+        // in gallery order, without the zoomed duplicate
+        assert_eq!(
+            details.images,
+            vec![
+                "https://m.media-amazon.com/images/I/41Qb7lO3kSL._AC_SY695_.jpg",
+                "https://m.media-amazon.com/images/I/51cXQ1kKJHL._AC_SY695_.jpg",
+                "https://m.media-amazon.com/images/I/61mZTz1pPZL._AC_SY695_.jpg",
+            ]
+        );
+        assert_eq!(
+            details.descr,
+            "Hochwertiger Edelstahl, nickelfrei\nBesetzt mit funkelnden Kristallen\nLieferung in einer Geschenkbox"
+        );
+        assert_eq!(details.brand.as_deref(), Some("s.Oliver"));
+        assert_eq!(details.material.as_deref(), Some("Edelstahl"));
+        assert_eq!(details.colour.as_deref(), Some("Silber"));
+
+        // the unavailable size is left out, the selected variant is listed once
+        assert_eq!(details.parent_asin.as_deref(), Some("B07FDPARNT"));
+        let variants: Vec<(&str, Vec<&str>, String)> = details
+            .variants
+            .iter()
+            .map(|variant| {
+                (
+                    variant.asin.as_str(),
+                    variant.dimensions.values().map(|v| v.as_str()).collect(),
+                    variant.price.amount.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            variants,
+            vec![
+                ("B07FD729LJ", vec!["Silber", "54"], "39.90".to_string()),
+                ("B07FD72ABC", vec!["Silber", "56"], "41.90".to_string()),
+                ("B07FD7ROSE", vec!["Roségold", "54"], "44.90".to_string()),
+            ]
+        );
+        let rose = &details.variants[2];
+        assert_eq!(
+            rose.details_link,
+            "https://www.amazon.de/dp/B07FD7ROSE?tag=glam0d9-21"
+        );
+        assert_eq!(
+            rose.img.as_deref(),
+            Some("https://m.media-amazon.com/images/I/51n7RoSeGL._SS36_.jpg")
+        );
+
+        // not a product page, e.g. a captcha
+        assert!(parse_details_page("<html><body></body></html>", profile, de).is_err());
+    }
+
     #[tokio::test]
     async fn insert_mock_info() -> Result<()> {
         let info = ProductInfo {
             name: "mock product 1".to_string(),
             details_link: "https://foo.bar/aaa".to_string(),
             price: Price {
-                str: "123.12".to_string(),
-                number: 123.12,
-                currency: "€".to_string(),
+                amount: BigDecimal::from_str("123.12").unwrap(),
+                currency: "EUR".to_string(),
             },
             img: "https://doesntexist.com/foo.png".to_string(),
+            ..Default::default()
         };
 
         let pool = init_pool("5433").await;
-        save_product_to_db(&pool, &info, "mock").await?;
+        let summary = save_products_to_db(
+            &pool,
+            &[info],
+            "mock",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
+        assert_eq!(summary.inserted + summary.updated, 1);
 
         Ok(())
     }
@@ -466,93 +1557,356 @@ mod test {
             name: "mock product 1".to_string(),
             details_link: "https://foo.bar/aaa".to_string(),
             price: Price {
-                str: "123.12".to_string(),
-                number: 123.12,
-                currency: "€".to_string(),
+                amount: BigDecimal::from_str("123.12").unwrap(),
+                currency: "EUR".to_string(),
             },
             img: "https://doesntexist.com/foo.png".to_string(),
+            ..Default::default()
         };
         let info2 = ProductInfo {
             name: "mock product 2".to_string(),
             details_link: "https://foo.bar/bbb".to_string(),
             price: Price {
-                str: "123.12".to_string(),
-                number: 123.12,
-                currency: "€".to_string(),
+                amount: BigDecimal::from_str("123.12").unwrap(),
+                currency: "EUR".to_string(),
             },
             img: "https://doesntexist.com/foo2.png".to_string(),
+            ..Default::default()
         };
         let pool = init_pool("5433").await;
-        save_products_to_db(&pool, &vec![info1, info2], "mock").await?;
+        let summary = save_products_to_db(
+            &pool,
+            &[info1, info2],
+            "mock",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
+        assert_eq!(summary.inserted + summary.updated, 2);
+        assert_eq!(summary.failed, 0);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn scrap_all() -> Result<()> {
-        let caps = DesiredCapabilities::chrome();
-        let driver = WebDriver::new("http://localhost:63374", caps).await?;
+    async fn insert_many_mock_infos_with_duplicates() -> Result<()> {
+        let mut infos: Vec<ProductInfo> = (0..1200)
+            .map(|i| ProductInfo {
+                name: format!("mock product {}", i),
+                details_link: format!("https://foo.bar/many/{}", i),
+                price: Price {
+                    amount: BigDecimal::from_str("9.99").unwrap(),
+                    currency: "EUR".to_string(),
+                },
+                img: format!("https://doesntexist.com/many/{}.png", i),
+                ..Default::default()
+            })
+            .collect();
+        infos.push(ProductInfo {
+            name: "mock product duplicate".to_string(),
+            details_link: "https://foo.bar/many/0".to_string(),
+            price: Price {
+                amount: BigDecimal::from_str("9.99").unwrap(),
+                currency: "EUR".to_string(),
+            },
+            img: "https://doesntexist.com/many/0.png".to_string(),
+            ..Default::default()
+        });
 
         let pool = init_pool("5433").await;
+        let summary = save_products_to_db(
+            &pool,
+            &infos,
+            "mock",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
+        assert_eq!(summary.inserted + summary.updated, 1200);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.failed, 0);
 
-        // let max_pages = u32::MAX; // get all pages
-        let max_pages = 4;
+        Ok(())
+    }
 
-        let necklace_url = "https://www.amazon.de/s?k=necklace&i=fashion";
-        let necklaces = extract_infos_for_all_pages(&driver, necklace_url, max_pages).await?;
-        println!("necklaces: {}", necklaces.len());
-        save_products_to_db(&pool, &necklaces, "necklace").await?;
+    #[tokio::test]
+    async fn saves_and_filters_by_attributes() -> Result<()> {
+        let mock_info = |link: &str, rating: f64, prime: bool| ProductInfo {
+            brand: Some("Mock Brand".to_string()),
+            rating: Some(rating),
+            review_count: Some(12),
+            prime,
+            ..product(link, "10.00")
+        };
 
-        let armband_url = "https://www.amazon.de/s?k=armband&i=fashion";
-        let armbands = extract_infos_for_all_pages(&driver, armband_url, max_pages).await?;
-        println!("armbands: {}", armbands.len());
-        save_products_to_db(&pool, &armbands, "armband").await?;
+        let pool = init_pool("5433").await;
+        save_products_to_db(
+            &pool,
+            &[
+                mock_info("https://foo.bar/attributes/a", 4.5, true),
+                mock_info("https://foo.bar/attributes/b", 3.0, false),
+            ],
+            "mock-attributes",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
 
-        let ring_url = "https://www.amazon.de/s?k=ringe&i=fashion";
-        let rings = extract_infos_for_all_pages(&driver, ring_url, max_pages).await?;
-        println!("rings: {}", rings.len());
-        save_products_to_db(&pool, &rings, "ring").await?;
+        let filters = Filters {
+            type_: vec!["mock-attributes".to_string()],
+            brand: vec!["mock brand".to_string()],
+            min_rating: Some(4.0),
+            ..Default::default()
+        };
+        let items = load_items(&pool, 0, &to_db_filters(&filters, &[])).await?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].vendor_link, "https://foo.bar/attributes/a");
+        assert_eq!(items[0].brand.as_deref(), Some("Mock Brand"));
+        assert_eq!(items[0].review_count, Some(12));
+        assert!(items[0].prime);
+
+        let filters = Filters {
+            type_: vec!["mock-attributes".to_string()],
+            prime: true,
+            ..Default::default()
+        };
+        let items = load_items(&pool, 0, &to_db_filters(&filters, &[])).await?;
+        assert_eq!(items.len(), 1);
 
-        let earring_url = "https://www.amazon.de/s?k=earring&i=fashion";
-        let earrings = extract_infos_for_all_pages(&driver, earring_url, max_pages).await?;
-        println!("earrings: {}", earrings.len());
-        save_products_to_db(&pool, &earrings, "earring").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clears_sponsored_once_found_organic() -> Result<()> {
+        let mock_info = |sponsored: bool| ProductInfo {
+            sponsored,
+            ..product("https://foo.bar/sponsored", "10.00")
+        };
+        let pool = init_pool("5433").await;
+        sqlx::query(
+            "UPDATE item SET is_sponsored = TRUE WHERE vendor_link = 'https://foo.bar/sponsored';",
+        )
+        .execute(&pool)
+        .await?;
+        let is_sponsored = || async {
+            let (is_sponsored,): (bool,) = sqlx::query_as(
+                "SELECT is_sponsored FROM item WHERE vendor_link = 'https://foo.bar/sponsored';",
+            )
+            .fetch_one(&pool)
+            .await?;
+            anyhow::Ok(is_sponsored)
+        };
 
-        println!("finished saving products to db");
+        save_products_to_db(
+            &pool,
+            &[mock_info(true)],
+            "mock-sponsored",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
+        assert!(is_sponsored().await?);
+        save_products_to_db(
+            &pool,
+            &[mock_info(false)],
+            "mock-sponsored",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
+        assert!(!is_sponsored().await?);
+        save_products_to_db(
+            &pool,
+            &[mock_info(true)],
+            "mock-sponsored",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
+        assert!(!is_sponsored().await?);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn scrap() -> Result<()> {
-        let caps = DesiredCapabilities::chrome();
-        let driver = WebDriver::new("http://localhost:64188", caps).await?;
+    async fn rejects_or_drafts_irrelevant_products() -> Result<()> {
+        let mock_info = |link: &str, name: &str| ProductInfo {
+            name: name.to_string(),
+            ..product(link, "10.00")
+        };
+        let relevance = RelevanceRules::parse(
+            r#"
+reject_below = 0.3
+publish_from = 0.6
+block = ["figur"]
+
+[category.mock-relevance]
+allow = ["halskette"]
+"#,
+        )?;
+
+        let pool = init_pool("5433").await;
+        sqlx::query(
+            "DELETE FROM item_pic WHERE item_id IN (SELECT id FROM item WHERE type_ = 'mock-relevance');",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("DELETE FROM item WHERE type_ = 'mock-relevance';")
+            .execute(&pool)
+            .await?;
 
-        // let root_url: &str = "https://www.amazon.de/s?k=ringe&i=fashion";
-        // only a few pages
-        let root_url: &str = "https://www.amazon.de/s?k=naruto+figurine";
+        let summary = save_products_to_db(
+            &pool,
+            &[
+                mock_info("https://foo.bar/relevance/a", "Halskette Silber"),
+                mock_info("https://foo.bar/relevance/b", "Naruto Figur"),
+                mock_info("https://foo.bar/relevance/c", "Geschenk für Frauen"),
+            ],
+            "mock-relevance",
+            "amazon.de",
+            &relevance,
+        )
+        .await?;
+        assert_eq!(summary.inserted, 2);
+        assert_eq!(summary.rejected, 1);
+        assert_eq!(summary.in_review, 1);
+
+        let statuses: Vec<(String, String, Option<f64>)> = sqlx::query_as(
+            "SELECT vendor_link, status, relevance_confidence FROM item WHERE type_ = 'mock-relevance' ORDER BY vendor_link;",
+        )
+        .fetch_all(&pool)
+        .await?;
+        assert_eq!(
+            statuses,
+            vec![
+                (
+                    "https://foo.bar/relevance/a".to_string(),
+                    "published".to_string(),
+                    Some(2.0 / 3.0)
+                ),
+                (
+                    "https://foo.bar/relevance/c".to_string(),
+                    "draft".to_string(),
+                    Some(0.5)
+                ),
+            ]
+        );
 
-        let infos = extract_infos_for_all_pages(&driver, root_url, 4).await?;
-        // println!("extracted links ({}) for all pages: {:?}", links.len(), links);
+        Ok(())
+    }
 
-        println!("extracted links ({}) for all pages", infos.len());
+    #[tokio::test]
+    async fn enqueues_price_drops_on_update() -> Result<()> {
+        let liked = "https://foo.bar/price-drop";
+        let not_liked = "https://foo.bar/price-drop/not-liked";
 
-        // to_csv(&infos)?;
         let pool = init_pool("5433").await;
-        save_products_to_db(&pool, &infos, "necklace").await?;
+        sqlx::query(
+            "DELETE FROM notification_outbox WHERE item_id IN (SELECT id FROM item WHERE type_ = 'mock-price-drop');",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query(
+            "DELETE FROM item_pic WHERE item_id IN (SELECT id FROM item WHERE type_ = 'mock-price-drop');",
+        )
+        .execute(&pool)
+        .await?;
+        sqlx::query("DELETE FROM item WHERE type_ = 'mock-price-drop';")
+            .execute(&pool)
+            .await?;
+
+        let save = |price: &str| {
+            let infos = [product(liked, price), product(not_liked, price)];
+            let pool = &pool;
+            async move {
+                save_products_to_db(
+                    pool,
+                    &infos,
+                    "mock-price-drop",
+                    "amazon.de",
+                    &RelevanceRules::default(),
+                )
+                .await
+            }
+        };
+        save("100.00").await?;
+        let (liked_id,): (i32,) = sqlx::query_as("SELECT id FROM item WHERE vendor_link = $1;")
+            .bind(liked)
+            .fetch_one(&pool)
+            .await?;
+        assert!(add_to_wishlist(&pool, "mock-user", liked_id).await?);
+        save("50.00").await?;
+        save("50.00").await?;
+
+        // only the wishlisted item is notified, once
+        let outbox: Vec<(i32, String, BigDecimal, BigDecimal)> = sqlx::query_as(
+            r#"
+SELECT item_id, kind, old_price_number, new_price_number
+FROM notification_outbox
+WHERE item_id IN (SELECT id FROM item WHERE type_ = 'mock-price-drop');
+"#,
+        )
+        .fetch_all(&pool)
+        .await?;
+        assert_eq!(
+            outbox,
+            vec![(
+                liked_id,
+                "price_drop".to_string(),
+                BigDecimal::from_str("100.00").unwrap(),
+                BigDecimal::from_str("50.00").unwrap()
+            )]
+        );
+
+        // unchanged prices aren't recorded again
+        let history: Vec<(BigDecimal,)> = sqlx::query_as(
+            "SELECT price_number FROM item_price_history WHERE item_id = $1 ORDER BY id;",
+        )
+        .bind(liked_id)
+        .fetch_all(&pool)
+        .await?;
+        assert_eq!(
+            history,
+            vec![
+                (BigDecimal::from_str("100.00").unwrap(),),
+                (BigDecimal::from_str("50.00").unwrap(),)
+            ]
+        );
 
-        // // collect details
-        // collect_details(&driver, &infos)
-        //     .await?;
+        Ok(())
+    }
 
-        // Keep the browser open by looping indefinitely
-        // enable this if needed to inspect something after finish
-        // loop {
-        //     tokio::time::sleep(std::time::Duration::from_secs(60)).await;
-        // }
+    #[tokio::test]
+    async fn diffs_products_with_db() -> Result<()> {
+        let pool = init_pool("5433").await;
+        save_products_to_db(
+            &pool,
+            &[
+                product("https://foo.bar/diff/a", "10.00"),
+                product("https://foo.bar/diff/b", "10.00"),
+            ],
+            "mock",
+            "amazon.de",
+            &RelevanceRules::default(),
+        )
+        .await?;
 
-        // Always explicitly close the browser.
-        // driver.quit().await?;
+        let diff = diff_products_with_db(
+            &pool,
+            &[
+                product("https://foo.bar/diff/a", "10.00"),
+                product("https://foo.bar/diff/b", "8.50"),
+                product("https://foo.bar/diff/doesntexist", "10.00"),
+            ],
+        )
+        .await?;
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].link, "https://foo.bar/diff/b");
+        assert_eq!(
+            diff.new,
+            vec!["https://foo.bar/diff/doesntexist".to_string()]
+        );
 
         Ok(())
     }
@@ -0,0 +1,235 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::dom::HtmlNode;
+
+/// The selector profiles of all page layouts, read from a toml file (see selectors.toml).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SelectorProfiles {
+    pub version: u32,
+    #[serde(rename = "profile")]
+    pub profiles: HashMap<String, SelectorProfile>,
+}
+
+/// The selector variants of a page layout, each field is tried in order until one matches.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SelectorProfile {
+    pub result_item: Vec<String>,
+    pub link_wrapper: Vec<String>,
+    pub link: Vec<String>,
+    pub name_wrapper: Vec<String>,
+    pub name: Vec<String>,
+    pub img: Vec<String>,
+    pub price_whole: Vec<String>,
+    pub price_fraction: Vec<String>,
+    pub price_symbol: Vec<String>,
+    pub next_page: Vec<String>,
+    pub next_page_disabled: Vec<String>,
+    pub brand: Vec<String>,
+    pub rating: Vec<String>,
+    pub review_count: Vec<String>,
+    pub prime: Vec<String>,
+    pub sponsored: Vec<String>,
+    pub details_thumbnail: Vec<String>,
+    pub details_image_wrapper: Vec<String>,
+    pub details_title: Vec<String>,
+    pub details_bullets: Vec<String>,
+    pub details_description: Vec<String>,
+    pub details_brand: Vec<String>,
+    pub details_material: Vec<String>,
+    pub details_colour: Vec<String>,
+    pub details_parent_asin: Vec<String>,
+    pub details_variation: Vec<String>,
+    pub details_variation_selection: Vec<String>,
+    pub details_variant: Vec<String>,
+    pub details_variant_value: Vec<String>,
+    pub details_variant_price: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectorField {
+    ResultItem,
+    LinkWrapper,
+    Link,
+    NameWrapper,
+    Name,
+    Img,
+    PriceWhole,
+    PriceFraction,
+    PriceSymbol,
+    NextPage,
+    NextPageDisabled,
+    Brand,
+    Rating,
+    ReviewCount,
+    Prime,
+    Sponsored,
+    DetailsThumbnail,
+    DetailsImageWrapper,
+    DetailsTitle,
+    DetailsBullets,
+    DetailsDescription,
+    DetailsBrand,
+    DetailsMaterial,
+    DetailsColour,
+    DetailsParentAsin,
+    DetailsVariation,
+    DetailsVariationSelection,
+    DetailsVariant,
+    DetailsVariantValue,
+    DetailsVariantPrice,
+}
+
+/// How often each selector variant matched, by field, e.g. {"price_whole": {".a-price-whole": 48}}.
+pub type SelectorMatches = BTreeMap<SelectorField, BTreeMap<String, u32>>;
+
+pub fn merge_matches(into: &mut SelectorMatches, matches: SelectorMatches) {
+    for (field, variants) in matches {
+        let counts = into.entry(field).or_default();
+        for (variant, count) in variants {
+            *counts.entry(variant).or_default() += count;
+        }
+    }
+}
+
+impl SelectorProfiles {
+    pub fn from_file(path: &Path) -> Result<SelectorProfiles> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("error reading {}: {}", path.display(), e))?;
+        SelectorProfiles::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<SelectorProfiles> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&SelectorProfile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| anyhow!("no selector profile: {}", name))
+    }
+}
+
+impl SelectorProfile {
+    pub fn variants(&self, field: SelectorField) -> &[String] {
+        match field {
+            SelectorField::ResultItem => &self.result_item,
+            SelectorField::LinkWrapper => &self.link_wrapper,
+            SelectorField::Link => &self.link,
+            SelectorField::NameWrapper => &self.name_wrapper,
+            SelectorField::Name => &self.name,
+            SelectorField::Img => &self.img,
+            SelectorField::PriceWhole => &self.price_whole,
+            SelectorField::PriceFraction => &self.price_fraction,
+            SelectorField::PriceSymbol => &self.price_symbol,
+            SelectorField::NextPage => &self.next_page,
+            SelectorField::NextPageDisabled => &self.next_page_disabled,
+            SelectorField::Brand => &self.brand,
+            SelectorField::Rating => &self.rating,
+            SelectorField::ReviewCount => &self.review_count,
+            SelectorField::Prime => &self.prime,
+            SelectorField::Sponsored => &self.sponsored,
+            SelectorField::DetailsThumbnail => &self.details_thumbnail,
+            SelectorField::DetailsImageWrapper => &self.details_image_wrapper,
+            SelectorField::DetailsTitle => &self.details_title,
+            SelectorField::DetailsBullets => &self.details_bullets,
+            SelectorField::DetailsDescription => &self.details_description,
+            SelectorField::DetailsBrand => &self.details_brand,
+            SelectorField::DetailsMaterial => &self.details_material,
+            SelectorField::DetailsColour => &self.details_colour,
+            SelectorField::DetailsParentAsin => &self.details_parent_asin,
+            SelectorField::DetailsVariation => &self.details_variation,
+            SelectorField::DetailsVariationSelection => &self.details_variation_selection,
+            SelectorField::DetailsVariant => &self.details_variant,
+            SelectorField::DetailsVariantValue => &self.details_variant_value,
+            SelectorField::DetailsVariantPrice => &self.details_variant_price,
+        }
+    }
+}
+
+/// Finds elements with a profile's selectors, counting which variant matched.
+pub struct SelectorMatcher<'a> {
+    profile: &'a SelectorProfile,
+    pub matches: SelectorMatches,
+}
+
+impl<'a> SelectorMatcher<'a> {
+    pub fn new(profile: &'a SelectorProfile) -> SelectorMatcher<'a> {
+        SelectorMatcher {
+            profile,
+            matches: SelectorMatches::new(),
+        }
+    }
+
+    /// The descendants matching the first variant that matches any, empty if none does.
+    pub fn find_all<N: HtmlNode>(&mut self, node: &N, field: SelectorField) -> Result<Vec<N>> {
+        for variant in self.profile.variants(field) {
+            let found = node.find_all(variant)?;
+            if !found.is_empty() {
+                *self
+                    .matches
+                    .entry(field)
+                    .or_default()
+                    .entry(variant.clone())
+                    .or_default() += 1;
+                return Ok(found);
+            }
+        }
+        Ok(vec![])
+    }
+
+    /// The first descendant matching any variant, error if there's none.
+    pub fn find<N: HtmlNode>(&mut self, node: &N, field: SelectorField) -> Result<N> {
+        self.find_all(node, field)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no element found for: {:?}", field))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use scraper::Html;
+
+    use crate::selectors::{SelectorField, SelectorMatcher, SelectorProfiles};
+
+    #[test]
+    fn falls_back_to_next_variant() {
+        let profiles = SelectorProfiles::parse(&include_str!("../selectors.toml").replace(
+            r#"price_whole = [".a-price-whole"]"#,
+            r#"price_whole = [".price-whole-b", ".a-price-whole"]"#,
+        ))
+        .unwrap();
+        let profile = profiles.get("amazon").unwrap();
+
+        let document = Html::parse_fragment(r#"<div><span class="a-price-whole">12</span></div>"#);
+        let mut matcher = SelectorMatcher::new(profile);
+        let whole = matcher
+            .find(&document.root_element(), SelectorField::PriceWhole)
+            .unwrap();
+        assert_eq!(whole.value().attr("class"), Some("a-price-whole"));
+        assert_eq!(
+            matcher.matches[&SelectorField::PriceWhole].get(".a-price-whole"),
+            Some(&1)
+        );
+
+        assert!(matcher
+            .find(&document.root_element(), SelectorField::PriceFraction)
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_profile() {
+        let profiles = SelectorProfiles::parse(include_str!("../selectors.toml")).unwrap();
+        assert!(profiles.get("ebay").is_err());
+    }
+}
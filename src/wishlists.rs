@@ -0,0 +1,74 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+
+// user ids are the clients', the push gateway resolves them to devices
+pub const MAX_USER_ID_LEN: usize = 128;
+
+/// Adds an item to a user's wishlist, its price drops are notified from then on (see notifications.rs).
+/// Returns false if there's no such item.
+pub async fn add_to_wishlist(pool: &Pool<Postgres>, user_id: &str, item_id: i32) -> Result<bool> {
+    // an item already on the wishlist keeps the time it was added
+    let res = sqlx::query(
+        r#"
+INSERT INTO wishlist_item (user_id, item_id, added_timestamp)
+SELECT $1, id, $3 FROM item WHERE id = $2
+ON CONFLICT (user_id, item_id) DO UPDATE SET added_timestamp = wishlist_item.added_timestamp;
+"#,
+    )
+    .bind(user_id)
+    .bind(item_id)
+    .bind(Utc::now().timestamp_micros())
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Returns false if the item wasn't on the user's wishlist.
+pub async fn remove_from_wishlist(
+    pool: &Pool<Postgres>,
+    user_id: &str,
+    item_id: i32,
+) -> Result<bool> {
+    let res = sqlx::query("DELETE FROM wishlist_item WHERE user_id = $1 AND item_id = $2;")
+        .bind(user_id)
+        .bind(item_id)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+
+    use crate::{
+        init_pool,
+        wishlists::{add_to_wishlist, remove_from_wishlist},
+    };
+
+    #[tokio::test]
+    async fn adds_and_removes_items() -> Result<()> {
+        let pool = init_pool("5433").await;
+        let (item_id,): (i32,) = sqlx::query_as(
+            r#"
+INSERT INTO item (name_, price_number, price_currency, vendor_link, type_, added_timestamp, descr)
+VALUES ('mock product wishlisted', 10, 'EUR', 'https://foo.bar/wishlisted', 'mock', 1, '')
+ON CONFLICT (vendor_link) DO UPDATE SET descr = ''
+RETURNING id;
+"#,
+        )
+        .fetch_one(&pool)
+        .await?;
+        remove_from_wishlist(&pool, "mock-user", item_id).await?;
+
+        assert!(add_to_wishlist(&pool, "mock-user", item_id).await?);
+        assert!(add_to_wishlist(&pool, "mock-user", item_id).await?);
+        assert!(!add_to_wishlist(&pool, "mock-user", -1).await?);
+
+        assert!(remove_from_wishlist(&pool, "mock-user", item_id).await?);
+        assert!(!remove_from_wishlist(&pool, "mock-user", item_id).await?);
+
+        Ok(())
+    }
+}